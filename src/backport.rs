@@ -0,0 +1,178 @@
+use regex::Regex;
+
+use record::{Record, MantainerDetails};
+use strategy::{StrategyError, VersionStrategy};
+use version::DebianVersion;
+
+
+/// Debian release codenames and their numbers, as used in "~bpo12+1" and
+/// "+deb12u1". Lint knows these as distributions too.
+pub const RELEASES: &'static [(&'static str, u32)] = &[
+    ("buster", 10),
+    ("bullseye", 11),
+    ("bookworm", 12),
+    ("trixie", 13),
+    ("forky", 14),
+    ("duke", 15),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Backport,
+    Security,
+}
+
+/// A backport or stable security update of the newest entry for one
+/// release.
+#[derive(Debug, Clone)]
+pub struct Derivation {
+    pub target: Target,
+    pub codename: String,
+    pub release: u32,
+}
+
+impl Derivation {
+    /// `None` for an unknown codename.
+    pub fn new(target: Target, codename: &str) -> Option<Derivation> {
+        RELEASES
+            .iter()
+            .find(|&&(name, _)| name == codename)
+            .map(|&(name, release)| Derivation {
+                target: target,
+                codename: name.to_string(),
+                release: release,
+            })
+    }
+
+    /// "bookworm-backports" or "bookworm-security".
+    pub fn distribution(&self) -> String {
+        match self.target {
+            Target::Backport => format!("{}-backports", self.codename),
+            Target::Security => format!("{}-security", self.codename),
+        }
+    }
+
+    fn suffix(&self, n: u64) -> String {
+        match self.target {
+            Target::Backport => format!("~bpo{}+{}", self.release, n),
+            Target::Security => format!("+deb{}u{}", self.release, n),
+        }
+    }
+
+    // Splits "1.2-3~bpo12+2" into ("1.2-3", 2) for backports of this
+    // release, "1.2-3+deb12u2" likewise for security updates.
+    fn split(&self, version: &DebianVersion) -> Option<(String, u64)> {
+        let pattern = match self.target {
+            Target::Backport => format!(r"^(?P<base>.+)~bpo{}\+(?P<number>\d+)$", self.release),
+            Target::Security => format!(r"^(?P<base>.+)\+deb{}u(?P<number>\d+)$", self.release),
+        };
+        let re = Regex::new(&pattern).unwrap();
+
+        re.captures(&version.to_string()).map(|cap| (
+            cap.name("base").unwrap().to_string(),
+            cap.name("number").unwrap().parse::<u64>().unwrap_or(0),
+        ))
+    }
+
+    // Version a derived one is based on: "1.2-3" for "1.2-3~bpo12+2" and
+    // "1.2-3+deb11u1", whatever the release.
+    fn base(version: &DebianVersion) -> Result<DebianVersion, StrategyError> {
+        let re = Regex::new(r"^(?P<base>.+?)(?:~bpo\d+\+\d+|\+deb\d+u\d+)$").unwrap();
+        let version = version.to_string();
+
+        match re.captures(&version) {
+            Some(cap) => Ok(try!(cap.name("base").unwrap().parse::<DebianVersion>())),
+            None => Ok(try!(version.parse::<DebianVersion>())),
+        }
+    }
+
+    /// Version strategy deriving from the entries in `records`.
+    pub fn strategy(&self, records: &[Record]) -> Derive {
+        Derive {
+            derivation: self.clone(),
+            versions: records.iter().map(|r| r.version.clone()).collect(),
+        }
+    }
+
+    /// Puts "Rebuild for bookworm-backports." first.
+    pub fn apply(&self, record: &mut Record) {
+        let bullet = format!("Rebuild for {}.", self.distribution());
+
+        if record.mantainer_details.iter().any(|md| md.details.contains(&bullet)) {
+            return;
+        }
+
+        match record.mantainer_details.first_mut() {
+            Some(md) if md.mantainer.is_empty() => {
+                md.details.insert(0, bullet);
+                return;
+            },
+            _ => {},
+        }
+
+        record.mantainer_details.insert(0, MantainerDetails::with_details(vec![bullet]));
+    }
+}
+
+/// Derived version for a `Derivation`. The base is the newest entry with
+/// any backport or security suffix stripped; the suffix number goes up
+/// with every derivation of the same base:
+///
+/// - backport: "1.2-3~bpo12+1", then "1.2-3~bpo12+2"; sorts below the base
+///   so that upgrading to the next release picks up the original
+/// - security: "1.2-3+deb12u1", then "1.2-3+deb12u2"; sorts above the base
+///
+/// Either way the new version has to sort above all earlier derivations
+/// for the release.
+pub struct Derive {
+    derivation: Derivation,
+    versions: Vec<DebianVersion>,
+}
+
+impl VersionStrategy for Derive {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let name = match self.derivation.target {
+            Target::Backport => "backport",
+            Target::Security => "security",
+        };
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Err(StrategyError::NoPrevious(name.to_string())),
+        };
+
+        let base = try!(Derivation::base(previous));
+
+        let n = self.versions
+            .iter()
+            .filter_map(|v| self.derivation.split(v))
+            .filter(|&(ref b, _)| *b == base.to_string())
+            .map(|(_, n)| n)
+            .max()
+            .unwrap_or(0);
+
+        let version = try!(format!("{}{}", base, self.derivation.suffix(n + 1))
+            .parse::<DebianVersion>());
+
+        let sorts = match self.derivation.target {
+            Target::Backport => version < base,
+            Target::Security => version > base,
+        };
+        if !sorts {
+            return Err(StrategyError::Unordered(version, base));
+        }
+
+        let earlier = self.versions
+            .iter()
+            .filter(|v| self.derivation.split(v).is_some())
+            .find(|v| **v >= version);
+        if let Some(earlier) = earlier {
+            return Err(StrategyError::Unordered(version, earlier.clone()));
+        }
+
+        Ok(version)
+    }
+
+    fn above_previous(&self) -> bool {
+        self.derivation.target == Target::Security
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use regex::Regex;
+
+
+/// A bug closed by a changelog entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Bug {
+    /// "Closes: #123" - Debian BTS
+    Debian(u32),
+    /// "LP: #123" - Launchpad
+    Launchpad(u32),
+}
+
+impl Bug {
+    pub fn number(&self) -> u32 {
+        match *self {
+            Bug::Debian(n) => n,
+            Bug::Launchpad(n) => n,
+        }
+    }
+}
+
+impl fmt::Display for Bug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Bug::Debian(n) => write!(f, "#{}", n),
+            Bug::Launchpad(n) => write!(f, "LP: #{}", n),
+        }
+    }
+}
+
+/// Compiled bug reference patterns, to search many details with.
+pub struct Finder {
+    closes: Regex,
+    lp: Regex,
+    number: Regex,
+}
+
+impl Finder {
+    pub fn new() -> Finder {
+        Finder {
+            closes: Regex::new(
+                r"(?i)closes:\s*(?:bug)?\#?\s?\d+(?:,\s*(?:bug)?\#?\s?\d+)*").unwrap(),
+            lp: Regex::new(r"(?i)lp:\s+\#\d+(?:,\s*\#\d+)*").unwrap(),
+            number: Regex::new(r"\#?\d+").unwrap(),
+        }
+    }
+
+    /// Finds bug references in change details, in the order they appear, as
+    /// `(start, end, bug)` where `start..end` covers the number and the "#"
+    /// right before it, if any.
+    ///
+    /// The expressions are the ones given by Debian Policy 4.4 and used by
+    /// dpkg and Launchpad; a detail may span several lines.
+    pub fn find(&self, text: &str) -> Vec<(usize, usize, Bug)> {
+        let mut found: Vec<(usize, usize, Bug)> = Vec::new();
+
+        for &(re, launchpad) in [(&self.closes, false), (&self.lp, true)].iter() {
+            for (start, end) in re.find_iter(text) {
+                for (s, e) in self.number.find_iter(&text[start..end]) {
+                    let number = text[start + s..start + e].trim_left_matches('#');
+                    if let Ok(n) = number.parse() {
+                        let bug = if launchpad { Bug::Launchpad(n) } else { Bug::Debian(n) };
+                        found.push((start + s, start + e, bug));
+                    }
+                }
+            }
+        }
+
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found
+    }
+
+    pub fn parse(&self, text: &str) -> Vec<Bug> {
+        self.find(text).into_iter().map(|(_, _, bug)| bug).collect()
+    }
+}
+
+/// `Finder::find` for a single text.
+pub fn find(text: &str) -> Vec<(usize, usize, Bug)> {
+    Finder::new().find(text)
+}
+
+/// `Finder::parse` for a single text.
+pub fn parse(text: &str) -> Vec<Bug> {
+    Finder::new().parse(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bug, Finder};
+
+    #[test]
+    fn finds_debian_and_launchpad_bugs_in_order() {
+        let finder = Finder::new();
+        assert_eq!(finder.parse("Fix it. LP: #42, #43\n  Closes: #1, bug#2"),
+                   vec![Bug::Launchpad(42), Bug::Launchpad(43), Bug::Debian(1), Bug::Debian(2)]);
+        assert_eq!(finder.find("closes: #7"), vec![(8, 10, Bug::Debian(7))]);
+        assert_eq!(finder.parse("no bugs, #3 alone"), vec![]);
+    }
+}
@@ -1,18 +1,251 @@
 use std::fs::File;
+use std::fmt;
+use std::str::FromStr;
+use std::collections::BTreeMap;
+use std::error;
+use std::io;
 use std::io::BufReader;
 use std::io::BufRead;
 use std::io::Write;
 use regex::Regex;
-use chrono::{DateTime,UTC};
+use chrono::DateTime;
+use chrono::format::ParseError;
+use bug::{Bug, Finder};
 use record::{Record, MantainerDetails};
+use select::Selection;
+use strategy::{StrategyError, VersionStrategy};
+use version::{DebianVersion, VersionError};
+use cst::{Kind, Line, Source};
+
+
+/// Construct the parser was looking for when it hit an unexpected line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expected {
+    Header,
+    Mantainer,
+    Detail,
+    Trailer,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expected::Header    => write!(f, "header \"package (version) distribution(s); \
+                                              key=value, ...\""),
+            Expected::Mantainer => write!(f, "\"[ mantainer ]\" block"),
+            Expected::Detail    => write!(f, "\"  * detail\" bullet"),
+            Expected::Trailer   => write!(f, "trailer \" -- name <email>  date\""),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ChangelogError {
+    IO(io::Error),
+    Date(String, ParseError),
+    Option(String),
+    Version(String, VersionError),
+    Unexpected(Expected),
+    UnexpectedEOF(Expected),
+}
+
+impl fmt::Display for ChangelogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChangelogError::IO(ref err) => write!(f, "failed to open or read \
+                                                      changelog: {}", err),
+            ChangelogError::Date(ref date, ref err) => write!(f, "failed to parse RFC2822 \
+                                                                  date \"{}\": {}", date, err),
+            ChangelogError::Option(ref option) => write!(f, "malformed header option \
+                                                             \"{}\", expected \
+                                                             key=value", option),
+            ChangelogError::Version(ref version, ref err) => write!(f, "invalid version \
+                                                                    \"{}\": {}", version, err),
+            ChangelogError::Unexpected(ref expected) => write!(f, "unexpected line, \
+                                                                   expected {}", expected),
+            ChangelogError::UnexpectedEOF(ref expected) => write!(f, "unexpected end of \
+                                                                      file, expected {}", expected),
+        }
+    }
+}
+
+impl From<io::Error> for ChangelogError {
+    fn from(err: io::Error) -> ChangelogError {
+        ChangelogError::IO(err)
+    }
+}
+
+impl error::Error for ChangelogError {
+    fn description(&self) -> &str {
+        match *self {
+            ChangelogError::IO(ref err) => err.description(),
+            ChangelogError::Date(_, ref err) => err.description(),
+            ChangelogError::Option(..) => "malformed header option",
+            ChangelogError::Version(_, ref err) => err.description(),
+            ChangelogError::Unexpected(..) => "unexpected line",
+            ChangelogError::UnexpectedEOF(..) => "unexpected end of file",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ChangelogError::IO(ref err) => Some(err),
+            ChangelogError::Date(_, ref err) => Some(err),
+            ChangelogError::Version(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BumpError {
+    Version(VersionError),
+    Strategy(StrategyError),
+    NotNewer(DebianVersion, DebianVersion),
+}
+
+impl fmt::Display for BumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BumpError::Version(ref err) => write!(f, "invalid new version: {}", err),
+            BumpError::Strategy(ref err) => write!(f, "failed to work out new version: {}", err),
+            BumpError::NotNewer(ref version, ref previous) => write!(f, "new version \
+                                                                        \"{}\" does not sort \
+                                                                        above previous \
+                                                                        version \"{}\"",
+                                                                        version, previous),
+        }
+    }
+}
+
+impl From<VersionError> for BumpError {
+    fn from(err: VersionError) -> BumpError {
+        BumpError::Version(err)
+    }
+}
+
+impl From<StrategyError> for BumpError {
+    fn from(err: StrategyError) -> BumpError {
+        BumpError::Strategy(err)
+    }
+}
+
+impl error::Error for BumpError {
+    fn description(&self) -> &str {
+        match *self {
+            BumpError::Version(ref err) => err.description(),
+            BumpError::Strategy(ref err) => err.description(),
+            BumpError::NotNewer(..) => "new version does not sort above previous version",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            BumpError::Version(ref err) => Some(err),
+            BumpError::Strategy(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A single parse problem located in a changelog file.
+///
+/// `line` and `column` are 1-based; `0` means the problem is not tied to
+/// a particular position (e.g. the file could not be opened).
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub error: ChangelogError,
+}
+
+impl Diagnostic {
+    pub fn new(path: &str, line: usize, column: usize, error: ChangelogError) -> Diagnostic {
+        Diagnostic {
+            path: path.to_string(),
+            line: line,
+            column: column,
+            error: error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.path, self.line, self.column, self.error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Header,    // between records, waiting for "package (version) ..."
+    Body,      // right after the header, no bullets yet
+    Mantainer, // right after "[ mantainer ]"
+    Detail,    // after "  * detail", continuation lines allowed
+}
+
+// `version` without a trailing "+N" and N, 0 if there is none.
+fn disambiguated(version: &DebianVersion) -> (DebianVersion, u64) {
+    let s = version.to_string();
+    if let Some(i) = s.rfind('+') {
+        if let (Ok(n), Ok(base)) = (s[i + 1..].parse::<u64>(), s[..i].parse::<DebianVersion>()) {
+            return (base, n);
+        }
+    }
+    (version.clone(), 0)
+}
+
+// Makes sure `version` sorts above `previous`. A version equal to the
+// previous one (two bumps within the same minute) can be told apart by a
+// "+N" suffix if `disambiguate` is set, N going up with every further
+// bump of the same version; a lower one is always an error.
+fn newer(version: DebianVersion, previous: &DebianVersion, disambiguate: bool)
+    -> Result<DebianVersion, BumpError>
+{
+    if version > *previous {
+        return Ok(version);
+    }
+
+    if disambiguate {
+        let (base, n) = disambiguated(previous);
+        let n = if version == base { n + 1 } else if version == *previous { 1 } else { 0 };
+        if n > 0 {
+            let candidate = try!(format!("{}+{}", version, n).parse::<DebianVersion>());
+            if candidate > *previous {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    Err(BumpError::NotNewer(version, previous.clone()))
+}
+
+// Lines outside of any record stick to the last record read, or to the
+// lines before the first record if there is none yet.
+fn trivia(leading: &mut Vec<Line>, records: &mut Vec<Record>, lines: Vec<Line>) {
+    match records.iter_mut().last() {
+        Some(record) => if let Some(ref mut source) = record.source {
+            source.lines.extend(lines);
+        },
+        None => leading.extend(lines),
+    }
+}
+
+fn column_of(line: &str) -> usize {
+    line.len() - line.trim_left().len() + 1
+}
 
 pub struct Changelog {
+    /// lines found before the first record
+    pub leading: Vec<Line>,
     pub records: Vec<Record>,
 }
 
 impl Changelog {
     pub fn new() -> Changelog {
         Changelog{
+            leading: Vec::new(),
             records: Vec::new(),
         }
     }
@@ -27,60 +260,132 @@ impl Changelog {
     pub fn to_string(&self) -> String {
         let mut s = String::new();
 
+        for line in self.leading.iter() {
+            s.push_str(&line.text);
+        }
+
         for record in self.records.iter() {
             s.push_str(&record.to_string());
-            s.push_str("\n");
+            if record.source.is_none() {
+                s.push_str("\n");
+            }
         }
 
         s
     }
 
+    pub fn select(&self, selection: &Selection) -> Vec<&Record> {
+        selection.apply(&self.records)
+    }
+
+    /// Maps every referenced bug to the versions closing it, newest first.
+    /// A bug with more than one version was closed twice.
+    pub fn bugs(&self) -> BTreeMap<Bug, Vec<DebianVersion>> {
+        let mut index: BTreeMap<Bug, Vec<DebianVersion>> = BTreeMap::new();
+        let finder = Finder::new();
+
+        for record in self.records.iter() {
+            for bug in record.find_bugs(&finder) {
+                index.entry(bug).or_insert_with(Vec::new).push(record.version.clone());
+            }
+        }
+
+        index
+    }
+
+    /// Version of the entry `up` would add next, checked against the newest
+    /// entry.
+    pub fn next_version(&self, strategy: &VersionStrategy, disambiguate: bool)
+        -> Result<DebianVersion, BumpError>
+    {
+        let previous = self.records.first().map(|r| &r.version);
+
+        let version = try!(strategy.next(previous));
+        match previous {
+            Some(previous) if strategy.above_previous() => newer(version, previous, disambiguate),
+            _ => Ok(version),
+        }
+    }
+
+    /// Adds a new newest entry. `version` has to sort above the current
+    /// newest entry unless `strategy` says otherwise, see `next_version`.
     pub fn up(&mut self,
+        strategy: &VersionStrategy,
+
         package: String,
-        version_template: String,
+        version: DebianVersion,
         distribution: String,
         urgency: String,
 
-        details: Vec<String>,
+        mantainer_details: Vec<MantainerDetails>,
 
         mantainer_name: String,
         mantainer_email: String,
-    ) {
-        let version = UTC::now().format(&version_template);
+    ) -> Result<(), BumpError> {
+        let version = match self.records.first() {
+            Some(previous) if strategy.above_previous() =>
+                try!(newer(version, &previous.version, false)),
+            _ => version,
+        };
 
         let mut record = Record::new();
         record.package = package;
-        record.version = version.to_string();
-        record.distribution = distribution;
-        record.urgency = urgency;
+        record.version = version;
+        record.distributions = distribution
+            .split_whitespace()
+            .map(|d| d.to_string())
+            .collect();
+        record.set_option("urgency", &urgency);
 
-        for detail in details {
-            record.mantainer_details
-                .iter_mut()
-                .last()
-                .unwrap()
-                    .details
-                    .push(detail);
-        }
+        record.mantainer_details = MantainerDetails::merge(mantainer_details);
 
         record.mantainer_name = mantainer_name;
         record.mantainer_email = mantainer_email;
 
         self.records.insert(0, record);
+        Ok(())
     }
 
-    pub fn from(&mut self, path: &str) {
+    /// Parses a changelog from any buffered reader; `name` is only used to
+    /// locate diagnostics ("<stdin>", a path inside an archive, ...).
+    pub fn from_reader<R: BufRead>(reader: R, name: &str) -> Result<Changelog, Vec<Diagnostic>> {
+        let mut changelog = Changelog::new();
+        try!(changelog.read(reader, name));
+        Ok(changelog)
+    }
+
+    pub fn from(&mut self, path: &str) -> Result<(), Vec<Diagnostic>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => return Err(vec![Diagnostic::new(path, 0, 0, ChangelogError::IO(err))]),
+        };
+
+        self.read(BufReader::new(file), path)
+    }
+
+    /// Appends records read from `reader` to the changelog. Nothing is
+    /// appended if there are errors.
+    pub fn read<R: BufRead>(&mut self, mut reader: R, path: &str) -> Result<(), Vec<Diagnostic>> {
+        // Debian Policy 5.6.12.1 / 4.4: "package (version) distribution(s); key=value, ..."
         let re1 = Regex::new(r"(?x)
             ^
-                (?P<package>[\w-]+)                           # package
-            \s
-                \((?P<version>[\d\w\s-_.=+:]+)\)              # version
-            \s
-                (?P<distribution>[\w\s\d]+); # distribution
-            \s
-                urgency=(?P<urgency>\w+)                      # urgency
+                (?P<package>\w[\w+.-]*)                      # package
+            \s+
+                \((?P<version>[^()\s]+)\)                    # version
+                (?P<distributions>(?:\s+[\w+.-]+)+)          # distribution(s)
+            \s*;\s*
+                (?P<options>.*?)                             # key=value, ...
+            \s*
         $").unwrap();
 
+        let re_option = Regex::new(r"(?x)
+            ^\s*
+                (?P<key>[\w-]+)   # key
+            \s*=\s*
+                (?P<value>.*?)    # value, may carry a comment: high (security)
+            \s*$
+        ").unwrap();
+
         let re2 = Regex::new(r"(?x)
             ^\s*\*\s*          # (  * ) - detail separator
                 (?P<detail>.*) # detail
@@ -101,147 +406,395 @@ impl Changelog {
                 (?P<date>[\d\w\s-_.=+:,]+)          # date
         $").unwrap();
 
-        match File::open(path) {
-            Err(why) => { println!("open file failed: {:?}", why); }
-            Ok(file) => {
-                let reader = BufReader::new(file);
-
-                let mut is_re1 = false;
-                let mut is_re2 = false;
-                let mut is_re3 = false;
-                let mut is_re4 = false;
+        let re_comment = Regex::new(r"^(?:#|/\*.*\*/|\$\w+:.*\$|vim:)").unwrap();
+        let re_footer = Regex::new(r"^(?i:(?:;;\s*)?local variables:)").unwrap();
+
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let mut footer = false;
+        let mut state = State::Header;
+        let mut accumulator = Record::new();
+        let mut lines: Vec<Line> = Vec::new(); // lines of the record being read
+        let mut leading: Vec<Line> = Vec::new();
+        let mut records: Vec<Record> = Vec::new();
+        let mut last_line = 0;
+
+        loop {
+            let mut text = String::new();
+            match reader.read_line(&mut text) {
+                Ok(0) => break,
+                Ok(..) => {},
+                Err(err) => {
+                    diagnostics.push(Diagnostic::new(path, last_line + 1, 0, ChangelogError::IO(err)));
+                    break;
+                },
+            };
+
+            let n = last_line + 1;
+            last_line = n;
+
+            let l = text.trim_right_matches(|c| c == '\n' || c == '\r').to_string();
+            let column = column_of(&l);
+            let inside = state != State::Header;
+
+            let kind = if l.trim().is_empty() {
+                Kind::Blank
+            } else if footer {
+                Kind::Comment
+            } else if let Some(cap) = re1.captures(&l) {
+                if state != State::Header {
+                    diagnostics.push(Diagnostic::new(path, n, column,
+                        ChangelogError::Unexpected(Expected::Trailer)));
+                }
 
-                let mut accumulator = Record::new();
+                accumulator = Record::new();
+                accumulator.package = cap.name("package").unwrap().to_string();
+                let version = cap.name("version").unwrap();
+                match version.parse::<DebianVersion>() {
+                    Ok(version) => accumulator.version = version,
+                    Err(err) => {
+                        let column = l.find(version).map_or(column, |i| i + 1);
+                        diagnostics.push(Diagnostic::new(path, n, column,
+                            ChangelogError::Version(version.to_string(), err)));
+                    },
+                }
+                accumulator.distributions = cap.name("distributions").unwrap()
+                    .split_whitespace()
+                    .map(|d| d.to_string())
+                    .collect();
+
+                let options = cap.name("options").unwrap();
+                for option in options.split(',') {
+                    match re_option.captures(option) {
+                        Some(cap) => accumulator.options.push((
+                            cap.name("key").unwrap().to_string(),
+                            cap.name("value").unwrap().to_string(),
+                        )),
+                        None => {
+                            let column = l.find(options).map_or(column, |i| i + 1);
+                            diagnostics.push(Diagnostic::new(path, n, column,
+                                ChangelogError::Option(option.trim().to_string())));
+                        },
+                    }
+                }
 
-                for line in reader.lines() {
-                    let l = &line.unwrap();
-                    if l == "" { continue; }; // FIXME
+                state = State::Body;
+                Kind::Header
+            } else if state == State::Header && (re_comment.is_match(&l) || re_footer.is_match(&l)) {
+                footer = re_footer.is_match(&l);
+                Kind::Comment
+            } else if state == State::Header {
+                diagnostics.push(Diagnostic::new(path, n, column,
+                    ChangelogError::Unexpected(Expected::Header)));
+                Kind::Unknown
+            } else if let Some(cap) = re4.captures(&l) {
+                let mantainer_name = cap.name("mantainer_name").unwrap().trim();
+                let mantainer_email = cap.name("mantainer_email").unwrap();
+                let date = cap.name("date").unwrap().trim();
+
+                accumulator.mantainer_name = mantainer_name.to_string();
+                accumulator.mantainer_email = mantainer_email.to_string();
+                match DateTime::parse_from_rfc2822(date) {
+                    Ok(date) => accumulator.date = date,
+                    Err(err) => {
+                        let column = l.rfind(date).map_or(column, |i| i + 1);
+                        diagnostics.push(Diagnostic::new(path, n, column,
+                            ChangelogError::Date(date.to_string(), err)));
+                    },
+                }
 
-                    let mut is_re2_local = false; // FIXME
+                state = State::Header;
+                Kind::Trailer
+            } else if let Some(cap) = re3.captures(&l) {
+                let mantainer = cap.name("mantainer").unwrap();
 
-                    for cap in re1.captures_iter(l) {
-                        is_re1 = true;
+                let got_one_more_mantainer = match accumulator.mantainer_details.iter_mut().last() {
+                    Some(md) if md.mantainer.is_empty() && md.details.is_empty() => {
+                        md.mantainer = mantainer.to_string();
+                        false
+                    }
+                    _ => true,
+                };
 
-                        let package = cap.name("package").unwrap_or("failed-to-find-package-name");
-                        let version = cap.name("version").unwrap_or("failed-to-find-package-version");
-                        let distribution = cap.name("distribution").unwrap();
-                        let urgency = cap.name("urgency").unwrap();
+                if got_one_more_mantainer {
+                    let mut md = MantainerDetails::new();
+                    md.mantainer = mantainer.to_string();
+                    accumulator.mantainer_details.push(md);
+                }
 
-                        accumulator.package = package.to_string();
-                        accumulator.version = version.to_string();
-                        accumulator.distribution = distribution.to_string();
-                        accumulator.urgency = urgency.to_string();
+                state = State::Mantainer;
+                Kind::Mantainer
+            } else if let Some(cap) = re2.captures(&l) {
+                let md = accumulator.mantainer_details.iter_mut().last().unwrap();
+                md.details.push(cap.name("detail").unwrap().to_string());
+
+                state = State::Detail;
+                Kind::Detail
+            } else if state == State::Detail && column > 1 {
+                let md = accumulator.mantainer_details.iter_mut().last().unwrap();
+                let detail = md.details.iter_mut().last().unwrap();
+                detail.push_str("\n");
+                detail.push_str(&l);
+                Kind::Continuation
+            } else {
+                let expected = if l.trim_left().starts_with("[") {
+                    Expected::Mantainer
+                } else {
+                    Expected::Detail
+                };
+                diagnostics.push(Diagnostic::new(path, n, column,
+                    ChangelogError::Unexpected(expected)));
+                Kind::Unknown
+            };
+
+            let line = Line { kind: kind, number: n, text: text };
+
+            match kind {
+                Kind::Header => {
+                    // an unterminated record is not kept, but its text is
+                    if inside {
+                        trivia(&mut leading, &mut records, lines);
                     }
+                    lines = vec![line];
+                },
+                Kind::Trailer => {
+                    lines.push(line);
+                    let rendered = accumulator.render();
+                    accumulator.source = Some(Source::new(lines, rendered));
+                    records.push(accumulator.copy());
+                    lines = Vec::new();
+                },
+                _ if inside => lines.push(line),
+                _ => trivia(&mut leading, &mut records, vec![line]),
+            }
+        }
 
-                    for cap in re2.captures_iter(l) {
-                        is_re2 = true;
-                        is_re2_local = true;
-
-                        match accumulator.mantainer_details.iter_mut().last() {
-                            Some(mantainer_details) => {
-                                mantainer_details
-                                .details
-                                .push(cap
-                                    .name("detail")
-                                    .unwrap()
-                                    .to_string());
-                            }
-                            None => panic!("mantainer-details is empty")
-                        }
-                    }
+        if state != State::Header {
+            diagnostics.push(Diagnostic::new(path, last_line + 1, 1,
+                ChangelogError::UnexpectedEOF(Expected::Trailer)));
+            trivia(&mut leading, &mut records, lines);
+        }
 
-                    for cap in re3.captures_iter(l) {
-                        is_re3 = true;
-
-                        let mantainer = cap
-                            .name("mantainer")
-                            .unwrap();
-
-                        let mut got_one_more_mantainer = false;
-
-                        match accumulator.mantainer_details.iter_mut().last() {
-                            Some(md) => {
-                                if md.mantainer.is_empty() {
-                                    md.mantainer = String::from(mantainer.to_string());
-                                } else {
-                                    got_one_more_mantainer = true;
-                                }
-                            }
-                            None => panic!("mantainer-details is empty")
-                        }
-
-                        if got_one_more_mantainer {
-                            let mut md = MantainerDetails::new();
-                            md.mantainer = String::from(mantainer.to_string());
-                            accumulator.mantainer_details.push(md);
-                        }
-                    }
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
 
-                    for cap in re4.captures_iter(l) {
-                        is_re4 = true;
+        self.leading.extend(leading);
+        self.records.extend(records);
+        Ok(())
+    }
 
-                        let mantainer_name = cap.name("mantainer_name").unwrap().trim();
-                        let mantainer_email = cap.name("mantainer_email").unwrap();
-                        let date = DateTime::parse_from_rfc2822(cap.name("date").unwrap());
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.to_string().as_bytes())
+    }
 
-                        accumulator.mantainer_name = mantainer_name.to_string();
-                        accumulator.mantainer_email = mantainer_email.to_string();
-                        if !date.is_err() {
-                            accumulator.date = date.unwrap();
-                        } else {
-                            println!("failed to parse RFC2822: {}", cap.name("date").unwrap());
-                        }
-                    }
+    pub fn to(&mut self, path: &str) -> io::Result<()> {
+        let mut file = try!(File::create(path));
+        try!(self.write(&mut file));
+        file.sync_all()
+    }
+}
 
-                    if is_re1 && is_re2 && !is_re2_local && !is_re3 && !is_re4 {
-                        let mut md = accumulator
-                            .mantainer_details
-                            .iter_mut()
-                            .last()
-                            .unwrap();
-
-                        let mut detail = String::from(md
-                            .details
-                            .pop()
-                            .unwrap()
-                            .to_string());
-
-                        detail.push_str("\n");
-                        detail.push_str(l);
-                        md.details.push(detail);
-                    }
+impl FromStr for Changelog {
+    type Err = Vec<Diagnostic>;
 
-                    if is_re1 && is_re2 && is_re4 {
-                        self.records.push(accumulator.copy());
+    fn from_str(s: &str) -> Result<Changelog, Vec<Diagnostic>> {
+        Changelog::from_reader(s.as_bytes(), "<string>")
+    }
+}
 
-                        is_re1 = false;
-                        is_re2 = false;
-                        is_re3 = false;
-                        is_re4 = false;
+#[cfg(test)]
+mod tests {
+    use super::{newer, BumpError, Changelog};
+    use strategy::{StrategyError, VersionStrategy};
+    use version::DebianVersion;
 
-                        accumulator.mantainer_details.clear();
-                        accumulator.mantainer_details = vec![MantainerDetails::new()];
-                    };
-                }
-            }
-        };
+    fn v(s: &str) -> DebianVersion {
+        s.parse::<DebianVersion>().unwrap()
     }
 
-    pub fn to(&mut self, path: &str) {
-        match File::create(path) {
-            Err(why) => { println!("create file failed: {:?}", why); }
-            Ok(mut file) => {
-                let s = self.to_string();
+    // hands out a fixed version, like a backport does when `above` is unset
+    struct Fixed {
+        version: &'static str,
+        above: bool,
+    }
 
-                match file.write(s.as_bytes()){
-                    Ok(..) => {
-                        file.sync_all().ok().expect("sync all failed");
-                    }
-                    Err(why) => {
-                        println!("write to file failed: {}", why);
-                    }
-                }
+    impl VersionStrategy for Fixed {
+        fn next(&self, _: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+            Ok(v(self.version))
+        }
+
+        fn above_previous(&self) -> bool {
+            self.above
+        }
+    }
+
+    fn up(changelog: &mut Changelog, strategy: &Fixed) -> Result<(), BumpError> {
+        changelog.up(strategy,
+            "dch".to_string(), v(strategy.version), "unstable".to_string(), "low".to_string(),
+            Vec::new(), "John Doe".to_string(), "john@example.com".to_string())
+    }
+
+    fn bump(version: &str, previous: &str) -> Result<String, BumpError> {
+        newer(v(version), &v(previous), true).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn first_bump_in_a_minute_is_kept() {
+        assert_eq!(bump("20240101.1200-1", "20240101.1159-1").unwrap(), "20240101.1200-1");
+    }
+
+    #[test]
+    fn second_bump_in_a_minute_gets_plus_one() {
+        assert_eq!(bump("20240101.1200-1", "20240101.1200-1").unwrap(), "20240101.1200-1+1");
+    }
+
+    #[test]
+    fn third_bump_in_a_minute_gets_plus_two() {
+        assert_eq!(bump("20240101.1200-1", "20240101.1200-1+1").unwrap(), "20240101.1200-1+2");
+        assert_eq!(bump("20240101.1200-1", "20240101.1200-1+9").unwrap(), "20240101.1200-1+10");
+    }
+
+    #[test]
+    fn lower_version_is_refused() {
+        match bump("20240101.1159-1", "20240101.1200-1+1") {
+            Err(BumpError::NotNewer(..)) => {},
+            other => panic!("expected NotNewer, got {:?}", other),
+        }
+        match bump("1.0-1", "1.0-1+1+1") {
+            Err(BumpError::NotNewer(..)) => {},
+            other => panic!("expected NotNewer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn equal_version_is_refused_without_disambiguate() {
+        match newer(v("1.0-1"), &v("1.0-1"), false) {
+            Err(BumpError::NotNewer(..)) => {},
+            other => panic!("expected NotNewer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn up_refuses_a_version_not_above_the_newest_entry() {
+        let mut changelog = Changelog::new();
+        up(&mut changelog, &Fixed{version: "1.0-2", above: true}).unwrap();
+
+        for version in &["1.0-2", "1.0-1"] {
+            match up(&mut changelog, &Fixed{version: version, above: true}) {
+                Err(BumpError::NotNewer(..)) => {},
+                other => panic!("expected NotNewer, got {:?}", other),
             }
-        };
+        }
+        assert_eq!(changelog.records.len(), 1);
+
+        up(&mut changelog, &Fixed{version: "1.0-3", above: true}).unwrap();
+        assert_eq!(changelog.records[0].version.to_string(), "1.0-3");
+    }
+
+    #[test]
+    fn up_keeps_a_lower_version_if_the_strategy_allows_it() {
+        let mut changelog = Changelog::new();
+        up(&mut changelog, &Fixed{version: "1.0-2", above: true}).unwrap();
+        up(&mut changelog, &Fixed{version: "1.0-2~bpo12+1", above: false}).unwrap();
+        assert_eq!(changelog.records[0].version.to_string(), "1.0-2~bpo12+1");
+    }
+
+    #[test]
+    fn next_version_and_up_agree() {
+        let mut changelog = Changelog::new();
+        let strategy = Fixed{version: "1.0-1", above: true};
+        up(&mut changelog, &strategy).unwrap();
+
+        let version = changelog.next_version(&strategy, true).unwrap();
+        assert_eq!(version.to_string(), "1.0-1+1");
+        changelog.up(&strategy,
+            "dch".to_string(), version, "unstable".to_string(), "low".to_string(),
+            Vec::new(), "John Doe".to_string(), "john@example.com".to_string()).unwrap();
+        assert_eq!(changelog.records.len(), 2);
+    }
+
+    #[test]
+    fn failed_read_leaves_the_changelog_alone() {
+        let entry = "dch (1.0-1) unstable; urgency=low\n\n  * One.\n\n \
+                     -- John Doe <john@example.com>  Mon, 01 Jan 2024 10:00:00 +0000\n";
+        let mut changelog = Changelog::new();
+        changelog.read(entry.as_bytes(), "<string>").unwrap();
+
+        let broken = format!("# leading\n{}dch (1.0-2) unstable; urgency=low\n\n  * Two.\n",
+                             entry.replace("1.0-1", "1.0-0"));
+        assert!(changelog.read(broken.as_bytes(), "<string>").is_err());
+        assert_eq!(changelog.records.len(), 1);
+        assert!(changelog.leading.is_empty());
+        assert_eq!(changelog.to_string(), entry);
+    }
+
+    // CRLF and LF entries, comments between them and an Emacs footer
+    const MIXED: &'static str = "\
+foo (1.2-2) unstable; urgency=medium
+
+  [ Alice ]
+  * Fix the build
+    with a continuation line.
+
+  [ Bob ]
+  * Closes: #123456
+
+ -- Carol <carol@example.com>  Mon, 01 Jan 2024 10:00:00 +0100
+
+# a comment between entries
+
+foo (1.2-1) unstable; urgency=low\r
+\r
+  * Initial release.\r
+\r
+ -- Carol <carol@example.com>  Sun, 31 Dec 2023 09:00:00 +0000\r
+
+Local variables:
+mode: debian-changelog
+End:
+";
+
+    #[test]
+    fn round_trip_is_byte_exact() {
+        let changelog = MIXED.parse::<Changelog>().unwrap();
+
+        assert_eq!(changelog.records.len(), 2);
+        assert!(changelog.records.iter().all(|r| !r.is_modified()));
+        assert_eq!(changelog.to_string(), MIXED);
+    }
+
+    #[test]
+    fn edit_leaves_other_records_untouched() {
+        let mut changelog = MIXED.parse::<Changelog>().unwrap();
+        changelog.records[1].distributions = vec!["experimental".to_string()];
+
+        let s = changelog.to_string();
+        let (first, rest) = s.split_at(s.find("foo (1.2-1)").unwrap());
+        assert_eq!(first, &MIXED[..MIXED.find("foo (1.2-1)").unwrap()]);
+
+        // rewritten with its own "\r\n", the trailing lines after the trailer
+        // kept
+        assert_eq!(rest, "\
+foo (1.2-1) experimental; urgency=low\r
+\r
+  * Initial release.\r
+\r
+ -- Carol <carol@example.com>  Sun, 31 Dec 2023 09:00:00 +0000\r
+
+Local variables:
+mode: debian-changelog
+End:
+");
+    }
+
+    #[test]
+    fn edit_keeps_trailing_comments() {
+        let mut changelog = MIXED.parse::<Changelog>().unwrap();
+        changelog.records[0].set_option("urgency", "high");
+
+        let s = changelog.to_string();
+        assert!(s.starts_with("foo (1.2-2) unstable; urgency=high\n"));
+        assert!(s.contains("+0100\n\n# a comment between entries\n\nfoo (1.2-1)"));
+        assert!(s.ends_with(&MIXED[MIXED.find("foo (1.2-1)").unwrap()..]));
     }
 }
@@ -8,16 +8,34 @@ use std::collections::HashMap;
 use std::path::Path;
 use std::string::FromUtf8Error;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use yaml_rust::YamlLoader;
 use yaml_rust::scanner::ScanError;
 
+use backport::{Derivation, Target, RELEASES};
+use lint::LintConfig;
+use render::RenderConfig;
+use select::Selection;
+use upload::Upload;
+use version::DebianVersion;
+
 
 const APP_NAME:         &'static str = "dch";
 const AUTHOR_NAME:      &'static str = "Ivan Egorov";
 const AUTHOR_EMAIL:     &'static str = "vany.egorov@gmail.com";
 const DEFAULT_CONFIG:   &'static str = "~/.dchrc";
 
+// Formats of `export` and `import`, the default first; JSON needs the
+// "json" feature.
+#[cfg(feature = "json")]
+const EXPORT_FORMATS: &'static [&'static str] = &["json", "markdown", "html"];
+#[cfg(not(feature = "json"))]
+const EXPORT_FORMATS: &'static [&'static str] = &["markdown", "html"];
+#[cfg(feature = "json")]
+const IMPORT_FORMATS: &'static [&'static str] = &["json", "keepachangelog"];
+#[cfg(not(feature = "json"))]
+const IMPORT_FORMATS: &'static [&'static str] = &["keepachangelog"];
+
 
 #[derive(Debug)]
 pub enum ConfigError {
@@ -25,6 +43,9 @@ pub enum ConfigError {
     UTF8(FromUtf8Error),
     YAML(ScanError),
     YAMLMissingDocument,
+    Flag(String, String),
+    /// package and what is wrong with its "lint" settings
+    Lint(String, String),
 }
 
 impl fmt::Display for ConfigError {
@@ -38,6 +59,10 @@ impl fmt::Display for ConfigError {
                                                      config file: {}", err),
             ConfigError::YAMLMissingDocument => write!(f, "no yaml documents in \
                                                            config file"),
+            ConfigError::Flag(ref flag, ref err) => write!(f, "invalid value for \
+                                                               --{}: {}", flag, err),
+            ConfigError::Lint(ref package, ref err) => write!(f, "invalid lint settings \
+                                                                  of {}: {}", package, err),
         }
     }
 }
@@ -66,6 +91,8 @@ impl error::Error for ConfigError {
             ConfigError::IO(ref err) => err.description(),
             ConfigError::UTF8(ref err) => err.description(),
             ConfigError::YAML(ref err) => err.description(),
+            ConfigError::Flag(..) => "invalid flag value",
+            ConfigError::Lint(..) => "invalid lint settings",
             _ => "",
         }
     }
@@ -85,6 +112,8 @@ pub struct ConfigPackage {
     pub path: String,
     pub path_changelog: String,
     pub path_dchfile: String,
+    pub lint: LintConfig,
+    pub render: RenderConfig,
 }
 
 impl ConfigPackage {
@@ -109,11 +138,106 @@ pub struct ConfigMantainer {
     pub email: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Up,
+    Lint,
+    Parsechangelog,
+    Show,
+    Export,
+    Import,
+    Dchfile,
+}
+
+impl Command {
+    /// Commands whose output is meant for other programs and must not be
+    /// mixed with progress messages.
+    pub fn is_quiet(&self) -> bool {
+        *self == Command::Parsechangelog || *self == Command::Show || *self == Command::Export
+            || *self == Command::Dchfile
+    }
+}
+
 pub struct Config {
+    pub command: Command,
+    /// single field to print for `parsechangelog -S`
+    pub field: Option<String>,
+    /// entries to print, see `Selection`
+    pub selection: Selection,
+    /// format for `export` and `import`
+    pub format: String,
+    /// file `import` reads from
+    pub input: Option<String>,
+    /// kind of upload the bumped entries are for
+    pub upload: Upload,
+    /// backport or security update to derive from the newest entries
+    pub derivation: Option<Derivation>,
+    /// Dchfile profile to use instead of the one picked from the branch
+    pub profile: Option<String>,
+    /// git revision the commits for a new entry start after, instead of
+    /// the release of the previous entry
+    pub since: Option<String>,
     pub path: String,
     pub packages: Vec<String>,
     pub packages_all: HashMap<String, ConfigPackage>,
     pub mantainer: ConfigMantainer,
+    /// .mailmap-style file normalising git author names
+    pub mailmap: Option<String>,
+}
+
+fn arg_packages<'ar>() -> Arg<'ar, 'ar, 'ar, 'ar, 'ar, 'ar> {
+    Arg::with_name("PACKAGES")
+        .multiple(true)
+        .required(false)
+        .help("projects to work with")
+}
+
+// dpkg-parsechangelog compatible range options; negative numbers have to
+// be passed as "--count=-3"
+fn args_selection<'ar>() -> Vec<Arg<'ar, 'ar, 'ar, 'ar, 'ar, 'ar>> {
+    let arg = |name, help| Arg::with_name(name).long(name).help(help).takes_value(true);
+
+    vec![
+        arg("since", "entries newer than this version"),
+        arg("until", "entries older than this version"),
+        arg("from", "entries newer than or equal to this version"),
+        arg("to", "entries older than or equal to this version"),
+        arg("count", "number of entries, from the bottom if negative"),
+        arg("offset", "entries to skip, from the bottom if negative"),
+    ]
+}
+
+fn flag_version(flags: &ArgMatches, name: &str) -> Result<Option<DebianVersion>, ConfigError> {
+    match flags.value_of(name) {
+        Some(value) => match value.parse::<DebianVersion>() {
+            Ok(version) => Ok(Some(version)),
+            Err(err) => Err(ConfigError::Flag(name.to_string(), err.to_string())),
+        },
+        None => Ok(None),
+    }
+}
+
+fn flag_number(flags: &ArgMatches, name: &str) -> Result<Option<isize>, ConfigError> {
+    match flags.value_of(name) {
+        Some(value) => match value.parse::<isize>() {
+            Ok(number) => Ok(Some(number)),
+            Err(err) => Err(ConfigError::Flag(name.to_string(), err.to_string())),
+        },
+        None => Ok(None),
+    }
+}
+
+fn flags_selection(flags: &ArgMatches) -> Result<Selection, ConfigError> {
+    let mut it = Selection::new();
+
+    it.since = try!(flag_version(flags, "since"));
+    it.until = try!(flag_version(flags, "until"));
+    it.from = try!(flag_version(flags, "from"));
+    it.to = try!(flag_version(flags, "to"));
+    it.count = try!(flag_number(flags, "count"));
+    it.offset = try!(flag_number(flags, "offset"));
+
+    Ok(it)
 }
 
 impl Config {
@@ -132,16 +256,27 @@ impl Config {
             s.push_str(&format!("\t\t path: {}\n", app.path));
             s.push_str(&format!("\t\t path-changelog: {}\n", app.path_changelog));
             s.push_str(&format!("\t\t path-dchfile: {}\n", app.path_dchfile));
+            s.push_str(&format!("\t\t lint: {}\n", app.lint.to_string()));
         }
 
         s.push_str("mantainer:\n");
         s.push_str(&format!("\t name: {}\n", self.mantainer.name));
         s.push_str(&format!("\t email: {}\n", self.mantainer.email));
 
+        if let Some(ref mailmap) = self.mailmap {
+            s.push_str(&format!("mailmap: {}\n", mailmap));
+        }
+
+        if let Some(ref profile) = self.profile {
+            s.push_str(&format!("profile: {}\n", profile));
+        }
+
         s
     }
 
     pub fn new() -> Result<Config, ConfigError> {
+        let codenames: Vec<&str> = RELEASES.iter().map(|&(codename, _)| codename).collect();
+
         let flags = App::new(APP_NAME)
             .version(&crate_version!()[..])
             .author(&format!("{} <{}>", AUTHOR_NAME, AUTHOR_EMAIL))
@@ -154,8 +289,105 @@ impl Config {
                 .long("config")
                 .help("Sets a custom config file")
                 .takes_value(true))
+            .arg(Arg::with_name("UPLOAD")
+                .short("u")
+                .long("upload")
+                .help("kind of upload, sets version suffix and first bullet")
+                .possible_values(&["maintainer", "nmu", "qa", "team", "binnmu"])
+                .takes_value(true))
+            .arg(Arg::with_name("BACKPORT")
+                .long("backport")
+                .help("derives a backport to this release, e.g. bookworm")
+                .possible_values(&codenames)
+                .conflicts_with("SECURITY")
+                .takes_value(true))
+            .arg(Arg::with_name("SECURITY")
+                .long("security")
+                .help("derives a security update for this release, e.g. bookworm")
+                .possible_values(&codenames)
+                .takes_value(true))
+            .arg(Arg::with_name("PROFILE")
+                .short("p")
+                .long("profile")
+                .help("Dchfile profile to use instead of the one for the git branch")
+                .takes_value(true))
+            .arg(Arg::with_name("SINCE")
+                .long("since")
+                .help("git revision the commits for the new entry start after, \
+                       the release of the previous entry by default")
+                .takes_value(true))
+            .subcommand(SubCommand::with_name("lint")
+                .about("checks changelogs against Debian Policy and common mistakes")
+                .arg(arg_packages()))
+            .subcommand(SubCommand::with_name("parsechangelog")
+                .about("prints entries the way dpkg-parsechangelog does, \
+                        the latest one by default")
+                .arg(Arg::with_name("FIELD")
+                    .short("S")
+                    .long("show-field")
+                    .help("prints only the value of this field")
+                    .takes_value(true))
+                .arg(Arg::with_name("all")
+                    .long("all")
+                    .help("all entries instead of the latest one"))
+                .args(args_selection())
+                .arg(arg_packages()))
+            .subcommand(SubCommand::with_name("show")
+                .about("prints entries in debian/changelog format, all by default")
+                .args(args_selection())
+                .arg(arg_packages()))
+            .subcommand(SubCommand::with_name("export")
+                .about("prints entries as structured data, all by default")
+                .arg(Arg::with_name("FORMAT")
+                    .short("f")
+                    .long("format")
+                    .help("output format")
+                    .possible_values(EXPORT_FORMATS)
+                    .takes_value(true))
+                .args(args_selection())
+                .arg(arg_packages()))
+            .subcommand(SubCommand::with_name("import")
+                .about("rebuilds the changelog from exported data")
+                .arg(Arg::with_name("FORMAT")
+                    .short("f")
+                    .long("format")
+                    .help("input format")
+                    .possible_values(IMPORT_FORMATS)
+                    .takes_value(true))
+                .arg(Arg::with_name("INPUT")
+                    .short("i")
+                    .long("input")
+                    .help("file to read, \"-\" for stdin")
+                    .required(true)
+                    .takes_value(true))
+                .arg(arg_packages()))
+            .subcommand(SubCommand::with_name("dchfile")
+                .about("prints the Dchfile settings in effect, with extended files merged")
+                .arg(arg_packages()))
             .get_matches();
 
+        let (command, flags_command) = match flags.subcommand() {
+            ("lint", Some(flags_lint)) => (Command::Lint, flags_lint),
+            ("parsechangelog", Some(flags_parse)) => (Command::Parsechangelog, flags_parse),
+            ("show", Some(flags_show)) => (Command::Show, flags_show),
+            ("export", Some(flags_export)) => (Command::Export, flags_export),
+            ("import", Some(flags_import)) => (Command::Import, flags_import),
+            ("dchfile", Some(flags_dchfile)) => (Command::Dchfile, flags_dchfile),
+            _ => (Command::Up, &flags),
+        };
+
+        let mut selection = try!(flags_selection(flags_command));
+        if selection.is_empty() && command == Command::Parsechangelog
+            && !flags_command.is_present("all") {
+            selection = Selection::latest();
+        }
+
+        let derivation = match (flags.value_of("BACKPORT"), flags.value_of("SECURITY")) {
+            (Some(codename), _) => Derivation::new(Target::Backport, codename),
+            (_, Some(codename)) => Derivation::new(Target::Security, codename),
+            _ => None,
+        };
+
         let path = flags.value_of("CONFIG").unwrap_or(DEFAULT_CONFIG);
 
         let mut file = match File::open(path) {
@@ -182,7 +414,7 @@ impl Config {
             None => return Err(ConfigError::YAMLMissingDocument),
         };
 
-        let flag_packages = match flags.values_of("PACKAGES") {
+        let flag_packages = match flags_command.values_of("PACKAGES") {
             Some(packages) => packages,
             None => Vec::new(),
         };
@@ -191,6 +423,18 @@ impl Config {
         for package in flag_packages { packages.push(package.to_string()); };
 
         let mut it = Config {
+            command: command,
+            field: flags_command.value_of("FIELD").map(|f| f.to_string()),
+            selection: selection,
+            format: flags_command.value_of("FORMAT").unwrap_or(match command {
+                Command::Import => IMPORT_FORMATS[0],
+                _ => EXPORT_FORMATS[0],
+            }).to_string(),
+            input: flags_command.value_of("INPUT").map(|i| i.to_string()),
+            upload: flags.value_of("UPLOAD").and_then(Upload::parse).unwrap_or(Upload::Maintainer),
+            derivation: derivation,
+            profile: flags.value_of("PROFILE").map(|p| p.to_string()),
+            since: flags.value_of("SINCE").map(|s| s.to_string()),
             path: path.to_string(),
             packages: packages,
             packages_all: HashMap::new(),
@@ -198,14 +442,23 @@ impl Config {
                 name: yaml_config["mantainer"]["name"].as_str().unwrap().to_string(),
                 email: yaml_config["mantainer"]["email"].as_str().unwrap().to_string(),
             },
+            mailmap: yaml_config["mailmap"].as_str().map(|m| m.to_string()),
         };
 
         for (package_name, package_config) in yaml_config["packages"].as_hash().unwrap() {
+            let package_name = package_name.as_str().unwrap();
+            let lint = match LintConfig::from_yaml(&package_config["lint"]) {
+                Ok(lint) => lint,
+                Err(err) => return Err(ConfigError::Lint(package_name.to_string(), err)),
+            };
+
             let mut package = ConfigPackage {
-                name: package_name.as_str().unwrap().to_string(),
+                name: package_name.to_string(),
                 path: package_config["path"].as_str().unwrap().to_string(),
                 path_changelog: "".to_string(),
                 path_dchfile: "".to_string(),
+                lint: lint,
+                render: RenderConfig::from_yaml(&package_config["render"]),
             };
 
             package.path_changelog = package_config["path-changelog"]
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::error;
+
+use regex::Regex;
+
+use git::Commit;
+use version::{DebianVersion, VersionError};
+
+
+/// Semantic version component a commit asks to bump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+#[derive(Debug)]
+pub enum ConventionalError {
+    /// no feat, fix or breaking commits since the previous release
+    NothingToRelease,
+    /// upstream version is not "MAJOR.MINOR[.PATCH]"
+    NotSemver(String),
+    Version(VersionError),
+}
+
+impl fmt::Display for ConventionalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConventionalError::NothingToRelease => write!(f, "no feat, fix or breaking \
+                                                              commits since the previous release"),
+            ConventionalError::NotSemver(ref upstream) => write!(f, "upstream version \"{}\" \
+                                                                     is not a semantic version",
+                                                                 upstream),
+            ConventionalError::Version(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<VersionError> for ConventionalError {
+    fn from(err: VersionError) -> ConventionalError {
+        ConventionalError::Version(err)
+    }
+}
+
+impl error::Error for ConventionalError {
+    fn description(&self) -> &str {
+        match *self {
+            ConventionalError::NothingToRelease => "nothing to release",
+            ConventionalError::NotSemver(..) => "not a semantic version",
+            ConventionalError::Version(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ConventionalError::Version(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Compiled Conventional Commits patterns, to check many commits with.
+pub struct Parser {
+    subject: Regex,
+    footer: Regex,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser {
+            subject: Regex::new(r"(?x)
+                ^(?P<type>[a-zA-Z]+)                        # feat, fix, chore, ...
+                (?:\([^)]*\))?                              # (scope)
+                (?P<breaking>!)?
+                :\s
+            ").unwrap(),
+            footer: Regex::new(r"(?m)^BREAKING[ -]CHANGE:").unwrap(),
+        }
+    }
+
+    /// Bump requested by a Conventional Commits message: "feat" is minor,
+    /// "fix" is patch, "!" after the type or a "BREAKING CHANGE:" footer is
+    /// major. Other types and free-form subjects request nothing.
+    pub fn bump(&self, commit: &Commit) -> Option<Bump> {
+        let cap = match self.subject.captures(&commit.subject) {
+            Some(cap) => cap,
+            None => return None,
+        };
+
+        if cap.name("breaking").is_some() || self.footer.is_match(&commit.body) {
+            return Some(Bump::Major);
+        }
+
+        match &cap.name("type").unwrap().to_lowercase()[..] {
+            "feat" => Some(Bump::Minor),
+            "fix" => Some(Bump::Patch),
+            _ => None,
+        }
+    }
+}
+
+/// `Parser::bump` for a single commit.
+pub fn bump(commit: &Commit) -> Option<Bump> {
+    Parser::new().bump(commit)
+}
+
+/// Version following `previous` for the given commits. Before 1.0.0
+/// breaking changes bump the minor and features the patch version. The
+/// epoch is kept, the debian revision (if any) starts over at "1".
+///
+/// A pre-release such as "1.2.0~rc1" is finalized to "1.2.0" if that
+/// already covers the bump: any fix, features unless it is a patch
+/// release, breaking changes only for a major release.
+pub fn next(previous: &DebianVersion, commits: &[Commit]) -> Result<DebianVersion, ConventionalError> {
+    let re_semver = Regex::new(r"^(?P<major>\d+)\.(?P<minor>\d+)(?:\.(?P<patch>\d+))?").unwrap();
+    let parser = Parser::new();
+
+    let bump = match commits.iter().filter_map(|c| parser.bump(c)).max() {
+        Some(bump) => bump,
+        None => return Err(ConventionalError::NothingToRelease),
+    };
+
+    let upstream = previous.upstream();
+    let cap = match re_semver.captures(upstream) {
+        Some(cap) => cap,
+        None => return Err(ConventionalError::NotSemver(upstream.to_string())),
+    };
+
+    let number = |name| cap.name(name).map_or(0, |n: &str| n.parse::<u64>().unwrap_or(0));
+    let (mut major, mut minor, mut patch) = (number("major"), number("minor"), number("patch"));
+
+    let bump = match (major, bump) {
+        (0, Bump::Major) => Bump::Minor,
+        (0, _) => Bump::Patch,
+        (_, bump) => bump,
+    };
+
+    let finalize = upstream.contains('~') && match bump {
+        Bump::Major => minor == 0 && patch == 0,
+        Bump::Minor => patch == 0,
+        Bump::Patch => true,
+    };
+
+    if !finalize {
+        match bump {
+            Bump::Major => { major += 1; minor = 0; patch = 0; },
+            Bump::Minor => { minor += 1; patch = 0; },
+            Bump::Patch => { patch += 1; },
+        }
+    }
+
+    let upstream = format!("{}.{}.{}", major, minor, patch);
+    Ok(try!(DebianVersion::new(previous.epoch(), &upstream, previous.revision().map(|_| "1"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use git::Commit;
+    use version::DebianVersion;
+    use super::{bump, next, Bump};
+
+    fn commit(subject: &str, body: &str) -> Commit {
+        Commit {
+            sha: String::new(),
+            author_name: String::new(),
+            author_email: String::new(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    fn next_of(previous: &str, subjects: &[&str]) -> String {
+        let commits: Vec<Commit> = subjects.iter().map(|s| commit(s, "")).collect();
+        next(&previous.parse::<DebianVersion>().unwrap(), &commits).unwrap().to_string()
+    }
+
+    #[test]
+    fn bumps() {
+        assert_eq!(bump(&commit("feat(ui): add x", "")), Some(Bump::Minor));
+        assert_eq!(bump(&commit("fix: crash", "")), Some(Bump::Patch));
+        assert_eq!(bump(&commit("refactor!: api", "")), Some(Bump::Major));
+        assert_eq!(bump(&commit("refactor: api", "BREAKING CHANGE: gone")), Some(Bump::Major));
+        assert_eq!(bump(&commit("docs: readme", "")), None);
+        assert_eq!(bump(&commit("Fix the build", "")), None);
+    }
+
+    #[test]
+    fn next_versions() {
+        assert_eq!(next_of("1.2.3-2", &["fix: a", "docs: b"]), "1.2.4-1");
+        assert_eq!(next_of("1.2.3-2", &["fix: a", "feat: b"]), "1.3.0-1");
+        assert_eq!(next_of("1:1.2.3", &["feat!: b"]), "1:2.0.0");
+        assert_eq!(next_of("0.4.1-1", &["feat!: b"]), "0.5.0-1");
+        assert_eq!(next_of("0.4.1-1", &["feat: b"]), "0.4.2-1");
+    }
+
+    #[test]
+    fn prerelease_is_finalized() {
+        assert_eq!(next_of("1.2.0~rc1-1", &["fix: a"]), "1.2.0-1");
+        assert_eq!(next_of("1.2.0~rc1-1", &["feat: a"]), "1.2.0-1");
+        assert_eq!(next_of("1.2.0~rc1-1", &["feat!: a"]), "2.0.0-1");
+        assert_eq!(next_of("1.2.1~rc1-1", &["feat: a"]), "1.3.0-1");
+        assert_eq!(next_of("2.0.0~beta2", &["feat!: a"]), "2.0.0");
+        assert_eq!(next_of("0.5.0~rc1-1", &["feat: a"]), "0.5.0-1");
+    }
+
+    #[test]
+    fn nothing_to_release() {
+        let commits = vec![commit("docs: readme", "")];
+        assert!(next(&"1.0.0-1".parse::<DebianVersion>().unwrap(), &commits).is_err());
+    }
+}
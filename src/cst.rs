@@ -0,0 +1,127 @@
+/// Concrete syntax of a changelog: every physical line is kept together with
+/// its line terminator, so that untouched entries can be written back byte
+/// for byte.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Header,
+    Blank,
+    Mantainer,
+    Detail,
+    Continuation,
+    Trailer,
+    /// between records: "# comment", "vim: ..." or the Emacs
+    /// "Local variables:" block and everything after it, as dpkg skips them
+    Comment,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub kind: Kind,
+    pub number: usize,
+    /// original bytes of the line, including "\n" or "\r\n" if present
+    pub text: String,
+}
+
+/// Splits `text` into lines outside of any record, numbered from 1.
+pub fn lines(text: &str) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let kind = if rest[..end].trim().is_empty() { Kind::Blank } else { Kind::Unknown };
+        lines.push(Line {
+            kind: kind,
+            number: lines.len() + 1,
+            text: rest[..end].to_string(),
+        });
+        rest = &rest[end..];
+    }
+
+    lines
+}
+
+impl Line {
+    /// Line content without its terminator.
+    pub fn content(&self) -> &str {
+        self.text.trim_right_matches(|c| c == '\n' || c == '\r')
+    }
+}
+
+/// Original lines of a parsed record: the header, its body, the trailer and
+/// whatever follows the trailer up to the next header.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub lines: Vec<Line>,
+    /// canonical rendering of the record at parse time; if the record still
+    /// renders to this, it was not touched and `lines` are written instead
+    pub rendered: String,
+}
+
+impl Source {
+    pub fn new(lines: Vec<Line>, rendered: String) -> Source {
+        Source {
+            lines: lines,
+            rendered: rendered,
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut s = String::new();
+        for line in self.lines.iter() {
+            s.push_str(&line.text);
+        }
+        s
+    }
+
+    /// Line terminator the record was written with, that of its header.
+    pub fn terminator(&self) -> &str {
+        match self.lines.first() {
+            Some(line) if line.text.ends_with("\r\n") => "\r\n",
+            _ => "\n",
+        }
+    }
+
+    /// Lines after the trailer, blank lines and comments up to the next
+    /// record; they are kept when the record itself is rewritten.
+    pub fn trailing(&self) -> &[Line] {
+        match self.lines.iter().rposition(|l| l.kind == Kind::Trailer) {
+            Some(at) => &self.lines[at + 1..],
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lines, Kind, Line, Source};
+
+    #[test]
+    fn lines_keep_their_terminators() {
+        let lines = lines("one\r\n\ntwo");
+        let texts: Vec<&str> = lines.iter().map(|l| &l.text[..]).collect();
+        assert_eq!(texts, vec!["one\r\n", "\n", "two"]);
+        assert_eq!(lines[0].content(), "one");
+        assert_eq!(lines[1].kind, Kind::Blank);
+        assert_eq!(lines[2].number, 3);
+    }
+
+    #[test]
+    fn trailing_lines_and_terminator() {
+        let line = |kind: Kind, text: &str| Line { kind: kind, number: 0, text: text.to_string() };
+        let source = Source::new(vec![
+            line(Kind::Header, "foo (1.0-1) unstable; urgency=low\r\n"),
+            line(Kind::Trailer, " -- Carol <carol@example.com>  Mon, 01 Jan 2024 10:00:00 \
+                                 +0000\r\n"),
+            line(Kind::Blank, "\r\n"),
+            line(Kind::Comment, "# end\r\n"),
+        ], String::new());
+
+        assert_eq!(source.terminator(), "\r\n");
+        let trailing: Vec<&str> = source.trailing().iter().map(|l| &l.text[..]).collect();
+        assert_eq!(trailing, vec!["\r\n", "# end\r\n"]);
+        assert_eq!(Source::new(Vec::new(), String::new()).terminator(), "\n");
+    }
+}
@@ -2,13 +2,20 @@ use std::io;
 use std::io::Read;
 use std::fmt;
 use std::error;
+use std::fs;
 use std::fs::File;
+use std::path::Path;
 use std::convert::From;
 use std::string::FromUtf8Error;
 
-use yaml_rust::YamlLoader;
+use yaml_rust::{Yaml, YamlLoader};
+use yaml_rust::yaml::Hash;
 use yaml_rust::scanner::ScanError;
 
+use git;
+use marks::Marks;
+use series::{Series, DEFAULT_OUTPUT};
+
 
 #[derive(Debug)]
 pub enum DCHFileError {
@@ -16,6 +23,55 @@ pub enum DCHFileError {
     UTF8(FromUtf8Error),
     YAML(ScanError),
     YAMLMissingDocument,
+    /// the document is not a mapping of settings
+    NotMap(Option<(usize, usize)>),
+    /// required key, the type it should have, line and column of the
+    /// settings it is missing from
+    Missing(String, &'static str, Option<(usize, usize)>),
+    /// key, the type it should have, line and column of the offending value
+    Type(String, &'static str, Option<(usize, usize)>),
+    /// key, a known key it is probably a typo of, line and column
+    Unknown(String, Option<&'static str>, Option<(usize, usize)>),
+    UnknownSeries(String, Option<(usize, usize)>),
+    /// key that only makes sense at the top of a Dchfile, line and column
+    NotInProfile(String, Option<(usize, usize)>),
+    /// profile asked for and the profiles there are
+    UnknownProfile(String, Vec<String>),
+    /// files of an "extends" chain that leads back to its start
+    Cycle(Vec<String>),
+    /// error in a file the Dchfile extends, with the path of that file
+    In(String, Box<DCHFileError>),
+}
+
+impl DCHFileError {
+    /// 1-based line and column the error refers to, if any.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match *self {
+            DCHFileError::NotMap(position) => position,
+            DCHFileError::Missing(_, _, position) => position,
+            DCHFileError::Type(_, _, position) => position,
+            DCHFileError::Unknown(_, _, position) => position,
+            DCHFileError::UnknownSeries(_, position) => position,
+            DCHFileError::NotInProfile(_, position) => position,
+            DCHFileError::In(_, ref err) => err.position(),
+            _ => None,
+        }
+    }
+
+    /// File the error is in when it is not the Dchfile itself.
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            DCHFileError::In(ref path, _) => Some(path),
+            _ => None,
+        }
+    }
+
+    fn within(self, path: &str) -> DCHFileError {
+        match self {
+            DCHFileError::In(..) | DCHFileError::Cycle(..) => self,
+            _ => DCHFileError::In(path.to_string(), Box::new(self)),
+        }
+    }
 }
 
 impl fmt::Display for DCHFileError {
@@ -27,6 +83,28 @@ impl fmt::Display for DCHFileError {
             DCHFileError::YAML(ref err) => write!(f, "failed to parse YAML content of \
                                                       dchfile: {}", err),
             DCHFileError::YAMLMissingDocument => write!(f, "no yaml documents found in dchfile"),
+            DCHFileError::NotMap(..) => write!(f, "dchfile should be a map of settings"),
+            DCHFileError::Missing(ref key, expected, ..) => write!(f, "missing \"{}\", expected {}",
+                                                               key, expected),
+            DCHFileError::Type(ref key, expected, ..) => write!(f, "\"{}\" should be {}",
+                                                                key, expected),
+            DCHFileError::Unknown(ref key, Some(known), ..) => write!(f, "unknown key \"{}\", \
+                                                                         did you mean \"{}\"?",
+                                                                     key, known),
+            DCHFileError::Unknown(ref key, None, ..) => write!(f, "unknown key \"{}\"", key),
+            DCHFileError::UnknownSeries(ref series, ..) => write!(f, "unknown series \"{}\", \
+                                                                     give its version", series),
+            DCHFileError::NotInProfile(ref key, ..) => write!(f, "\"{}\" cannot be set in \
+                                                                 a profile", key),
+            DCHFileError::UnknownProfile(ref name, ref known) if known.is_empty() => {
+                write!(f, "unknown profile \"{}\", there are no profiles", name)
+            },
+            DCHFileError::UnknownProfile(ref name, ref known) => write!(f, "unknown profile \
+                                                                            \"{}\", known: {}",
+                                                                        name, known.join(", ")),
+            DCHFileError::Cycle(ref files) => write!(f, "\"extends\" cycle: {}",
+                                                     files.join(" -> ")),
+            DCHFileError::In(_, ref err) => err.fmt(f),
         }
     }
 }
@@ -55,7 +133,16 @@ impl error::Error for DCHFileError {
             DCHFileError::IO(ref err) => err.description(),
             DCHFileError::UTF8(ref err) => err.description(),
             DCHFileError::YAML(ref err) => err.description(),
-            _ => "",
+            DCHFileError::YAMLMissingDocument => "no yaml documents found",
+            DCHFileError::NotMap(..) => "not a map",
+            DCHFileError::Missing(..) => "missing key",
+            DCHFileError::Type(..) => "wrong type",
+            DCHFileError::Unknown(..) => "unknown key",
+            DCHFileError::UnknownSeries(..) => "unknown series",
+            DCHFileError::NotInProfile(..) => "key not allowed in a profile",
+            DCHFileError::UnknownProfile(..) => "unknown profile",
+            DCHFileError::Cycle(..) => "extends cycle",
+            DCHFileError::In(_, ref err) => err.description(),
         }
     }
 
@@ -64,20 +151,462 @@ impl error::Error for DCHFileError {
             DCHFileError::IO(ref err) => Some(err),
             DCHFileError::UTF8(ref err) => Some(err),
             DCHFileError::YAML(ref err) => Some(err),
+            DCHFileError::In(_, ref err) => Some(&**err),
             _ => None,
         }
     }
 }
 
+// Scalar as a command line argument; `[sleep, 1]` is a valid hook.
+fn argument(yaml: &Yaml) -> Option<String> {
+    match *yaml {
+        Yaml::String(ref s) => Some(s.to_string()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Real(ref r) => Some(r.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Scalar,
+    Bool,
+    Strings,
+    Commands,
+    Series,
+    Paths,
+    Merge,
+    Profiles,
+}
+
+impl Type {
+    fn expected(&self) -> &'static str {
+        match *self {
+            Type::Scalar => "a string",
+            Type::Bool => "true or false",
+            Type::Strings => "a list of strings",
+            Type::Commands => "a list of commands, each a list of arguments",
+            Type::Series => "a list of series names or name/version maps",
+            Type::Paths => "a path or a list of paths",
+            Type::Merge => "\"append\" or \"replace\"",
+            Type::Profiles => "a map of profile names to settings",
+        }
+    }
+
+    // Path of the first node of `yaml` (at `path`) that does not fit.
+    fn mismatch(&self, yaml: &Yaml, path: &str) -> Option<String> {
+        let item = |i: usize| format!("{}.{}", path, i);
+        let item_named = |name: &str| format!("{}.{}", path, name);
+
+        let items = match (*self, yaml) {
+            (_, &Yaml::Null) => return None,
+            (Type::Scalar, _) => return argument(yaml).map_or(Some(path.to_string()), |_| None),
+            (Type::Bool, &Yaml::Boolean(..)) => return None,
+            (Type::Bool, _) => return Some(path.to_string()),
+            (Type::Merge, _) => return match yaml.as_str() {
+                Some("append") | Some("replace") => None,
+                _ => Some(path.to_string()),
+            },
+            (Type::Paths, &Yaml::String(..)) => return None,
+            (Type::Profiles, &Yaml::Hash(ref profiles)) => return profiles
+                .iter()
+                .find(|&(_, profile)| profile.as_hash().is_none() && !profile.is_null())
+                .map(|(name, _)| item_named(&scalar(name, ""))),
+            (_, &Yaml::Array(ref items)) => items,
+            _ => return Some(path.to_string()),
+        };
+
+        for (i, it) in items.iter().enumerate() {
+            let fits = match *self {
+                Type::Strings => argument(it).is_some(),
+                Type::Commands => match it.as_vec() {
+                    Some(argv) => {
+                        if let Some(j) = argv.iter().position(|a| argument(a).is_none()) {
+                            return Some(format!("{}.{}", item(i), j));
+                        }
+                        !argv.is_empty()
+                    },
+                    None => false,
+                },
+                Type::Series => it.as_str().is_some() || it["name"].as_str().is_some(),
+                Type::Paths => it.as_str().is_some(),
+                _ => true,
+            };
+            if !fits {
+                return Some(item(i));
+            }
+        }
+
+        None
+    }
+}
+
+/// Keys a Dchfile may have.
+const KEYS: &'static [(&'static str, Type)] = &[
+    ("extends", Type::Paths),
+    ("details-merge", Type::Merge),
+    ("package", Type::Scalar),
+    ("version", Type::Scalar),
+    ("version-strategy", Type::Scalar),
+    ("distribution", Type::Scalar),
+    ("urgency", Type::Scalar),
+    ("details", Type::Strings),
+    ("changelog-md", Type::Scalar),
+    ("changelog-md-release", Type::Scalar),
+    ("git-log", Type::Bool),
+    ("git-ignore", Type::Strings),
+    ("disambiguate", Type::Bool),
+    ("series", Type::Series),
+    ("series-output", Type::Scalar),
+    ("before", Type::Commands),
+    ("after", Type::Commands),
+    ("profiles", Type::Profiles),
+    ("profile-from-branch", Type::Bool),
+];
+
+/// Keys a profile cannot override.
+const NOT_IN_PROFILE: &'static [&'static str] = &["extends", "profiles", "profile-from-branch"];
+
+// Levenshtein distance, for suggesting the key a typo was meant to be.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let above = row[j + 1];
+            let cost = if ca == b[j] { 0 } else { 1 };
+            row[j + 1] = *[above + 1, row[j] + 1, diagonal + cost].iter().min().unwrap();
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+// Checks the keys of `map`, found at `prefix` of the document, against
+// `KEYS`; `prefix` is empty for the top level and names a profile else.
+fn check(map: &Hash, prefix: &str, marks: &Marks, errors: &mut Vec<DCHFileError>) {
+    for (key, value) in map.iter() {
+        let key = match argument(key) {
+            Some(key) => key,
+            None => continue,
+        };
+        let path = if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+
+        if !prefix.is_empty() && NOT_IN_PROFILE.contains(&&key[..]) {
+            errors.push(DCHFileError::NotInProfile(key.to_string(), marks.key(&path)));
+            continue;
+        }
+
+        match KEYS.iter().find(|&&(name, _)| name == key) {
+            Some(&(_, kind)) => if let Some(at) = kind.mismatch(value, &path) {
+                let position = marks.value(&at);
+                errors.push(DCHFileError::Type(path.to_string(), kind.expected(), position));
+            },
+            None => {
+                let known = KEYS
+                    .iter()
+                    .map(|&(name, _)| (distance(&key, name), name))
+                    .filter(|&(d, _)| d <= 2)
+                    .min()
+                    .map(|(_, name)| name);
+                errors.push(DCHFileError::Unknown(path.to_string(), known, marks.key(&path)));
+            },
+        }
+
+        if key == "series" {
+            for (i, item) in value.as_vec().map_or(&[][..], |v| &v[..]).iter().enumerate() {
+                if Series::from_yaml(item).is_none() {
+                    let name = item.as_str().or(item["name"].as_str()).unwrap_or("");
+                    let position = marks.value(&format!("{}.{}", path, i));
+                    errors.push(DCHFileError::UnknownSeries(name.to_string(), position));
+                }
+            }
+        }
+    }
+}
+
+/// Checks `yaml` and its profiles against `KEYS`, every problem found is
+/// returned. Keys that may come from an extended file or a profile are
+/// not required here.
+fn validate(yaml: &Yaml, marks: &Marks) -> Vec<DCHFileError> {
+    let mut errors: Vec<DCHFileError> = Vec::new();
+
+    let map = match yaml.as_hash() {
+        Some(map) => map,
+        None => return vec![DCHFileError::NotMap(marks.value(""))],
+    };
+    check(map, "", marks, &mut errors);
+
+    if let Some(profiles) = yaml["profiles"].as_hash() {
+        for (name, profile) in profiles.iter() {
+            if let Some(profile) = profile.as_hash() {
+                check(profile, &format!("profiles.{}", scalar(name, "")), marks, &mut errors);
+            }
+        }
+    }
+
+    errors
+}
+
+// Keys the merged Dchfile cannot do without; `marks` are those of the
+// Dchfile itself, a missing key is reported at the top of its settings.
+fn required(yaml: &Yaml, marks: &Marks) -> Vec<DCHFileError> {
+    let mut errors: Vec<DCHFileError> = Vec::new();
+
+    let strategy = yaml["version-strategy"].as_str().unwrap_or("template");
+    if (strategy == "template" || strategy == "new-upstream") && yaml["version"].is_badvalue() {
+        errors.push(DCHFileError::Missing("version".to_string(), Type::Scalar.expected(),
+                                          marks.value("")));
+    }
+
+    errors
+}
+
+// Reads and validates the single file at `path`.
+fn load(path: &str) -> Result<(Yaml, Marks), Vec<DCHFileError>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => return Err(vec![DCHFileError::IO(err)]),
+    };
+
+    let mut file_content_utf8 = Vec::new();
+    if let Err(err) = file.read_to_end(&mut file_content_utf8) {
+        return Err(vec![DCHFileError::IO(err)])
+    };
+    let file_content = match String::from_utf8(file_content_utf8) {
+        Ok(file_content) => file_content,
+        Err(err) => return Err(vec![DCHFileError::UTF8(err)]),
+    };
+
+    let mut yamls = match YamlLoader::load_from_str(&file_content) {
+        Ok(yamls) => yamls,
+        Err(err) => return Err(vec![DCHFileError::YAML(err)]),
+    };
+    if yamls.is_empty() {
+        return Err(vec![DCHFileError::YAMLMissingDocument]);
+    }
+    let yaml = yamls.swap_remove(0);
+
+    let marks = match Marks::load(&file_content) {
+        Ok(marks) => marks,
+        Err(err) => return Err(vec![DCHFileError::YAML(err)]),
+    };
+
+    let errors = validate(&yaml, &marks);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok((yaml, marks))
+}
+
+// `child` over `base`: every key of `child` overrides the inherited one
+// except "details", which are appended to the inherited details unless
+// `child` has "details-merge: replace", and "profiles", which override
+// inherited profiles of the same name only.
+fn merge(base: Yaml, child: Yaml) -> Yaml {
+    let details = Yaml::String("details".to_string());
+    let replace = child["details-merge"].as_str() == Some("replace");
+
+    let mut merged = match base {
+        Yaml::Hash(hash) => hash,
+        _ => Hash::new(),
+    };
+    if replace {
+        merged.remove(&details);
+    }
+
+    if let Yaml::Hash(child) = child {
+        for (key, value) in child {
+            match key.as_str() {
+                Some("extends") | Some("details-merge") => continue,
+                Some("profiles") => {
+                    let mut profiles = merged.get(&key).and_then(|p| p.as_hash()).cloned()
+                        .unwrap_or(Hash::new());
+                    if let Yaml::Hash(child) = value {
+                        profiles.extend(child);
+                    }
+                    merged.insert(key, Yaml::Hash(profiles));
+                },
+                Some("details") if !replace => {
+                    let mut items = merged.get(&key).and_then(|d| d.as_vec())
+                        .map_or(Vec::new(), |d| d.to_vec());
+                    items.extend(value.as_vec().map_or(Vec::new(), |d| d.to_vec()));
+                    merged.insert(key, Yaml::Array(items));
+                },
+                _ => {
+                    merged.insert(key, value);
+                },
+            }
+        }
+    }
+
+    Yaml::Hash(merged)
+}
+
+fn canonicalize(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or(path.to_string())
+}
+
+// The Dchfile at `path` merged over the files it extends, in the order
+// given; those are relative to `path` and may extend further files.
+// `chain` holds the files being resolved, to catch cycles, `files` gets
+// every file merged, bases first. A file extended twice, like a common
+// base of two extended files, is merged at its first place only. The
+// marks returned are those of `path`.
+fn resolve(path: &str, chain: &mut Vec<String>, files: &mut Vec<String>)
+    -> Result<(Yaml, Marks), Vec<DCHFileError>>
+{
+    let canonical = canonicalize(path);
+    if let Some(at) = chain.iter().position(|p| *p == canonical) {
+        let mut cycle = chain[at..].to_vec();
+        cycle.push(canonical);
+        return Err(vec![DCHFileError::Cycle(cycle)]);
+    }
+
+    let (yaml, marks) = try!(load(path));
+
+    let parents = match yaml["extends"] {
+        Yaml::String(ref parent) => vec![parent.to_string()],
+        ref parents => strings(parents),
+    };
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    chain.push(canonical);
+    let mut base = Yaml::Hash(Hash::new());
+    for parent in parents.iter() {
+        let parent = dir.join(parent).to_string_lossy().into_owned();
+        if files.iter().any(|f| canonicalize(f) == canonicalize(&parent)) {
+            continue;
+        }
+        match resolve(&parent, chain, files) {
+            Ok((yaml, _)) => base = merge(base, yaml),
+            Err(errors) => {
+                return Err(errors.into_iter().map(|e| e.within(&parent)).collect());
+            },
+        }
+    }
+    chain.pop();
+
+    files.push(path.to_string());
+    Ok((merge(base, yaml), marks))
+}
+
+fn scalar(yaml: &Yaml, default: &str) -> String {
+    argument(yaml).unwrap_or(default.to_string())
+}
+
+fn strings(yaml: &Yaml) -> Vec<String> {
+    yaml.as_vec().map_or(Vec::new(), |items| items.iter().filter_map(argument).collect())
+}
+
+// "before" and "after" hooks:
+//
+//     before:
+//       - [make, check]
+//       - [git, diff, --exit-code]
+fn hooks(yaml: &Yaml) -> Vec<Vec<String>> {
+    let mut it: Vec<Vec<String>> = Vec::new();
+
+    if let Some(hooks) = yaml.as_vec() {
+        for hook in hooks.iter().filter_map(|h| h.as_vec()) {
+            it.push(hook.iter().filter_map(argument).collect());
+        }
+    }
+
+    it
+}
+
+// Picks the profile named `profile` or, with "profile-from-branch", the one
+// named after the DEP-14 suite of the branch `branch` returns if there is
+// such a profile, and merges it over the rest of the settings like an
+// extending file. `branch` is only called for "profile-from-branch".
+fn apply(mut yaml: Yaml, profile: Option<&str>, branch: &Fn() -> Option<String>)
+    -> Result<(Option<String>, Yaml), DCHFileError>
+{
+    let name = match profile {
+        Some(name) => name.to_string(),
+        None => {
+            if !yaml["profile-from-branch"].as_bool().unwrap_or(false) {
+                return Ok((None, yaml));
+            }
+            match branch().as_ref().and_then(|b| git::suite(b)) {
+                Some(suite) if !yaml["profiles"][suite].is_badvalue() => suite.to_string(),
+                _ => return Ok((None, yaml)),
+            }
+        },
+    };
+
+    let settings = match yaml["profiles"][&name[..]] {
+        Yaml::BadValue => {
+            let known = yaml["profiles"].as_hash().map_or(Vec::new(), |profiles| {
+                profiles.keys().map(|name| scalar(name, "")).collect()
+            });
+            return Err(DCHFileError::UnknownProfile(name, known));
+        },
+        ref settings => settings.clone(),
+    };
+
+    if let Yaml::Hash(ref mut map) = yaml {
+        map.remove(&Yaml::String("profiles".to_string()));
+        map.remove(&Yaml::String("profile-from-branch".to_string()));
+    }
+
+    Ok((Some(name), merge(yaml, settings)))
+}
+
 pub struct DCHFile {
+    /// files the settings were merged from, extended ones first and the
+    /// Dchfile itself last
+    pub files: Vec<String>,
+    /// profile the settings were taken from, see `profiles`
+    pub profile: Option<String>,
+
     pub package: String,
+    /// strftime template for the "template" version strategy, upstream
+    /// version for "new-upstream" and "rc"; rendered with `template` first
     pub version: String,
+    /// how the new version is worked out: "template" (the default),
+    /// "revision", "new-upstream", "daily", "rc" or "conventional-commits",
+    /// see `strategy`
+    pub version_strategy: String,
     pub distribution: String,
     pub urgency: String,
 
+    /// templates, see `template`
     pub details: Vec<String>,
 
+    /// Keep a Changelog markdown file, relative to the package, whose
+    /// release notes are added to `details`
+    pub changelog_md: Option<String>,
+    /// release of `changelog_md` to use, the newest released one if not set
+    pub changelog_md_release: Option<String>,
+
+    /// add subjects of the commits since the previous release to `details`
+    pub git_log: bool,
+    /// regular expressions for commit subjects to leave out
+    pub git_ignore: Vec<String>,
+
+    /// append "+N" to a version equal to the previous one instead of failing
+    pub disambiguate: bool,
+
+    /// Ubuntu series the bump fans out to, one changelog variant each
+    pub series: Vec<Series>,
+    /// where the variants go, relative to the package; "{series}" is
+    /// replaced with the series name
+    pub series_output: String,
+
+    /// commands, as argv lists, run once the new version is known and
+    /// before anything is written; a failing one stops the bump
     pub before: Vec<Vec<String>>,
+    /// commands run after the changelogs are written
     pub after: Vec<Vec<String>>,
 }
 
@@ -85,10 +614,49 @@ impl DCHFile {
     pub fn to_string(&mut self) -> String {
         let mut s = String::new();
 
+        if self.files.len() > 1 {
+            s.push_str(&format!("files:\n"));
+            for file in self.files.iter() {
+                s.push_str(&format!("\t - {}\n", file));
+            }
+        }
+        if let Some(ref profile) = self.profile {
+            s.push_str(&format!("profile: {}\n", profile));
+        }
         s.push_str(&format!("package: {}\n", self.package));
         s.push_str(&format!("version: {}\n", self.version));
+        s.push_str(&format!("version-strategy: {}\n", self.version_strategy));
         s.push_str(&format!("distribution: {}\n", self.distribution));
         s.push_str(&format!("urgency: {}\n", self.urgency));
+        s.push_str(&format!("disambiguate: {}\n", self.disambiguate));
+        s.push_str(&format!("git-log: {}\n", self.git_log));
+        if !self.git_ignore.is_empty() {
+            s.push_str(&format!("git-ignore:\n"));
+            for pattern in self.git_ignore.iter() {
+                s.push_str(&format!("\t - {}\n", pattern));
+            }
+        }
+        if !self.series.is_empty() {
+            s.push_str(&format!("series:\n"));
+            for series in self.series.iter() {
+                s.push_str(&format!("\t - {} ({})\n", series.name, series.version));
+            }
+            s.push_str(&format!("series-output: {}\n", self.series_output));
+        }
+        for &(key, ref hooks) in [("before", &self.before), ("after", &self.after)].iter() {
+            if !hooks.is_empty() {
+                s.push_str(&format!("{}:\n", key));
+                for argv in hooks.iter() {
+                    s.push_str(&format!("\t - {}\n", argv.join(" ")));
+                }
+            }
+        }
+        if let Some(ref changelog_md) = self.changelog_md {
+            s.push_str(&format!("changelog-md: {}\n", changelog_md));
+        }
+        if let Some(ref release) = self.changelog_md_release {
+            s.push_str(&format!("changelog-md-release: {}\n", release));
+        }
 
         s.push_str(&format!("details:\n"));
         for detail in self.details.iter_mut() {
@@ -98,50 +666,358 @@ impl DCHFile {
         s
     }
 
-    pub fn new(path: &str, name: &str) -> Result<DCHFile, DCHFileError> {
-        let mut file = match File::open(path) {
-            Ok(file) => file,
-            Err(err) => return Err(DCHFileError::IO(err)),
+    /// Reads and validates the Dchfile at `path` together with the files
+    /// it extends; `name` is the package name used when none of them sets
+    /// one.
+    ///
+    /// A Dchfile can have named profiles, each overriding some of the
+    /// settings:
+    ///
+    /// ```yaml
+    /// version: "%Y%m%d-1"
+    /// profile-from-branch: true
+    /// profiles:
+    ///   unstable:
+    ///     distribution: unstable
+    ///   staging:
+    ///     distribution: staging
+    ///     urgency: low
+    ///     details:
+    ///       - "Staging build of {{ git.sha }}."
+    /// ```
+    ///
+    /// `profile` is used if given, otherwise with "profile-from-branch" the
+    /// profile named after the DEP-14 suite of the checked out branch, if
+    /// there is one: "debian/unstable" picks "unstable". `branch` returns
+    /// the checked out branch and is only called in that case.
+    pub fn new(path: &str, name: &str, profile: Option<&str>, branch: &Fn() -> Option<String>)
+        -> Result<DCHFile, Vec<DCHFileError>>
+    {
+        let mut files: Vec<String> = Vec::new();
+        let (yaml, marks) = try!(resolve(path, &mut Vec::new(), &mut files));
+        let (profile, yaml) = match apply(yaml, profile, branch) {
+            Ok(applied) => applied,
+            Err(err) => return Err(vec![err]),
         };
 
-        let mut file_content_utf8 = Vec::new();
-        if let Err(err) = file.read_to_end(&mut file_content_utf8) {
-            return Err(DCHFileError::IO(err))
-        };
-        let file_content = match String::from_utf8(file_content_utf8) {
-            Ok(file_content) => file_content,
-            Err(err) => return Err(DCHFileError::UTF8(err)),
-        };
+        let errors = required(&yaml, &marks);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(DCHFile {
+            files: files,
+            profile: profile,
+
+            package: scalar(&yaml["package"], name),
+            version: scalar(&yaml["version"], ""),
+            version_strategy: scalar(&yaml["version-strategy"], "template"),
+            distribution: scalar(&yaml["distribution"], "stable"),
+            urgency: scalar(&yaml["urgency"], "medium"),
+
+            details: strings(&yaml["details"]),
+
+            changelog_md: argument(&yaml["changelog-md"]),
+            changelog_md_release: argument(&yaml["changelog-md-release"]),
+
+            git_log: yaml["git-log"].as_bool().unwrap_or(false),
+            git_ignore: strings(&yaml["git-ignore"]),
+
+            disambiguate: yaml["disambiguate"].as_bool().unwrap_or(false),
+
+            series: yaml["series"].as_vec().map_or(Vec::new(), |items| {
+                items.iter().filter_map(Series::from_yaml).collect()
+            }),
+            series_output: scalar(&yaml["series-output"], DEFAULT_OUTPUT),
+
+            before: hooks(&yaml["before"]),
+            after: hooks(&yaml["after"]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+
+    use yaml_rust::YamlLoader;
+
+    use marks::Marks;
+    use super::{apply, distance, required, validate, DCHFile, DCHFileError};
+
+    // Writes `files` to a fresh directory named after the test, returns
+    // the path of the first one.
+    fn write(test: &str, files: &[(&str, &str)]) -> String {
+        let dir = env::temp_dir().join(format!("dch-dchfile-{}", test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for &(name, content) in files.iter() {
+            File::create(dir.join(name)).unwrap().write_all(content.as_bytes()).unwrap();
+        }
+        dir.join(files[0].0).to_string_lossy().into_owned()
+    }
+
+    fn names(dchfile: &DCHFile) -> Vec<String> {
+        dchfile.files.iter().map(|f| f.rsplit('/').next().unwrap().to_string()).collect()
+    }
+
+    fn errors(s: &str) -> Vec<DCHFileError> {
+        let yaml = YamlLoader::load_from_str(s).unwrap().swap_remove(0);
+        validate(&yaml, &Marks::load(s).unwrap())
+    }
+
+    fn error(s: &str) -> DCHFileError {
+        let mut errors = errors(s);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        errors.remove(0)
+    }
 
-        let yamls = match YamlLoader::load_from_str(&file_content) {
-            Ok(yamls) => yamls,
-            Err(err) => return Err(DCHFileError::YAML(err)),
+    #[test]
+    fn distance_counts_edits() {
+        assert_eq!(distance("version", "version"), 0);
+        assert_eq!(distance("verison", "version"), 2);
+        assert_eq!(distance("urgncy", "urgency"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "git-log"), 7);
+    }
+
+    #[test]
+    fn unknown_key_suggests_a_close_one() {
+        match error("package: dch\nverison: \"%Y%m%d\"\n") {
+            DCHFileError::Unknown(ref key, Some("version"), Some((2, 1))) if key == "verison" => {},
+            other => panic!("expected Unknown verison at 2:1, got {:?}", other),
+        }
+        match error("package: dch\n  # no\nmaintainer: me\n") {
+            DCHFileError::Unknown(ref key, None, Some((3, 1))) if key == "maintainer" => {},
+            other => panic!("expected Unknown maintainer at 3:1, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrong_type_points_at_the_value() {
+        match error("git-log: maybe\n") {
+            DCHFileError::Type(ref key, "true or false", Some((1, 10))) if key == "git-log" => {},
+            other => panic!("expected Type git-log at 1:10, got {:?}", other),
+        }
+        match error("details:\n  - one\n  - [two]\n") {
+            DCHFileError::Type(ref key, "a list of strings", Some((3, 5)))
+                if key == "details" => {},
+            other => panic!("expected Type details at 3:5, got {:?}", other),
+        }
+        match error("after:\n  - [make, {a: b}]\n") {
+            DCHFileError::Type(ref key, _, Some((2, 12))) if key == "after" => {},
+            other => panic!("expected Type after at 2:12, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let errors = errors("verison: 1\ngit-log: 1\nurgency: [low]\n");
+        let positions: Vec<_> = errors.iter().map(|e| e.position()).collect();
+        assert_eq!(positions, vec![Some((1, 1)), Some((2, 10)), Some((3, 10))]);
+    }
+
+    #[test]
+    fn not_a_map() {
+        match error("- version\n") {
+            DCHFileError::NotMap(Some((1, 1))) => {},
+            other => panic!("expected NotMap at 1:1, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_version_points_at_the_settings() {
+        let s = "# nightly builds\npackage: dch\n";
+        let yaml = YamlLoader::load_from_str(s).unwrap().swap_remove(0);
+        let errors = required(&yaml, &Marks::load(s).unwrap());
+        match errors.first() {
+            Some(&DCHFileError::Missing(ref key, _, Some((2, 1)))) if key == "version" => {},
+            other => panic!("expected Missing version at 2:1, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn details_are_appended_to_the_extended_ones() {
+        let path = write("append", &[
+            ("Dchfile", "extends: base.yaml\ndetails: [child]\n"),
+            ("base.yaml", "version: \"%Y%m%d-1\"\ndetails: [base]\n"),
+        ]);
+        let dchfile = DCHFile::new(&path, "dch", None, &|| None).unwrap();
+        assert_eq!(dchfile.details, vec!["base", "child"]);
+        assert_eq!(names(&dchfile), vec!["base.yaml", "Dchfile"]);
+    }
+
+    #[test]
+    fn details_merge_replace_drops_the_extended_ones() {
+        let path = write("replace", &[
+            ("Dchfile", "extends: base.yaml\ndetails-merge: replace\ndetails: [child]\n"),
+            ("base.yaml", "version: \"%Y%m%d-1\"\ndetails: [base]\n"),
+        ]);
+        let dchfile = DCHFile::new(&path, "dch", None, &|| None).unwrap();
+        assert_eq!(dchfile.details, vec!["child"]);
+    }
+
+    #[test]
+    fn later_extends_override_earlier_ones() {
+        let path = write("order", &[
+            ("Dchfile", "extends: [one.yaml, two.yaml]\nurgency: high\n"),
+            ("one.yaml", "version: \"%Y%m%d-1\"\ndistribution: one\nurgency: low\n\
+                          package: one\n"),
+            ("two.yaml", "distribution: two\nurgency: medium\n"),
+        ]);
+        let dchfile = DCHFile::new(&path, "dch", None, &|| None).unwrap();
+        assert_eq!(dchfile.package, "one");
+        assert_eq!(dchfile.distribution, "two");
+        assert_eq!(dchfile.urgency, "high");
+        assert_eq!(names(&dchfile), vec!["one.yaml", "two.yaml", "Dchfile"]);
+    }
+
+    #[test]
+    fn common_base_is_merged_once() {
+        let path = write("diamond", &[
+            ("Dchfile", "extends: [left.yaml, right.yaml]\n"),
+            ("left.yaml", "extends: base.yaml\ndetails: [left]\n"),
+            ("right.yaml", "extends: base.yaml\ndetails: [right]\n"),
+            ("base.yaml", "version: \"%Y%m%d-1\"\ndetails: [base]\n"),
+        ]);
+        let dchfile = DCHFile::new(&path, "dch", None, &|| None).unwrap();
+        assert_eq!(names(&dchfile), vec!["base.yaml", "left.yaml", "right.yaml", "Dchfile"]);
+        assert_eq!(dchfile.details, vec!["base", "left", "right"]);
+    }
+
+    #[test]
+    fn extends_cycle_is_an_error() {
+        let path = write("cycle", &[
+            ("Dchfile", "extends: a.yaml\nversion: \"%Y%m%d-1\"\n"),
+            ("a.yaml", "extends: b.yaml\n"),
+            ("b.yaml", "extends: [a.yaml]\n"),
+        ]);
+        let errors = match DCHFile::new(&path, "dch", None, &|| None) {
+            Ok(..) => panic!("expected a cycle"),
+            Err(errors) => errors,
         };
+        assert_eq!(errors.len(), 1);
+        match errors[0] {
+            DCHFileError::Cycle(ref files) => {
+                let files: Vec<&str> = files.iter().map(|f| f.rsplit('/').next().unwrap())
+                    .collect();
+                assert_eq!(files, vec!["a.yaml", "b.yaml", "a.yaml"]);
+            },
+            ref other => panic!("expected Cycle, got {:?}", other),
+        }
+        assert_eq!(errors[0].path(), None);
+    }
 
-        let yaml = match yamls.first() {
-            Some(yaml) => yaml,
-            None => return Err(DCHFileError::YAMLMissingDocument),
+    #[test]
+    fn error_in_extended_file_names_it() {
+        let path = write("within", &[
+            ("Dchfile", "extends: base.yaml\nversion: \"%Y%m%d-1\"\n"),
+            ("base.yaml", "\nurgncy: low\n"),
+        ]);
+        let errors = match DCHFile::new(&path, "dch", None, &|| None) {
+            Ok(..) => panic!("expected an error"),
+            Err(errors) => errors,
         };
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].path().unwrap().ends_with("base.yaml"));
+        assert_eq!(errors[0].position(), Some((2, 1)));
+    }
 
-        let mut it = DCHFile {
-            package: yaml["package"].as_str().unwrap_or(&name).to_string(),
-            version: yaml["version"].as_str().unwrap().to_string(),
-            distribution: yaml["distribution"].as_str().unwrap_or("stable").to_string(),
-            urgency: yaml["urgency"].as_str().unwrap_or("medium").to_string(),
+    #[test]
+    fn valid_dchfile_has_no_errors() {
+        assert!(errors("version: \"%Y%m%d-1\"\n\
+                        details:\n  - Nightly build.\n\
+                        git-log: true\n\
+                        before:\n  - [sleep, 1]\n\
+                        profiles:\n  staging:\n    urgency: low\n").is_empty());
+    }
 
-            details: Vec::new(),
+    #[test]
+    fn profiles_are_checked() {
+        match error("profiles:\n  staging:\n    urgncy: low\n") {
+            DCHFileError::Unknown(ref key, Some("urgency"), Some((3, 5)))
+                if key == "profiles.staging.urgncy" => {},
+            other => panic!("expected Unknown in profile at 3:5, got {:?}", other),
+        }
+        match error("profiles:\n  staging:\n    extends: base.yaml\n") {
+            DCHFileError::NotInProfile(ref key, Some((3, 5))) if key == "extends" => {},
+            other => panic!("expected NotInProfile at 3:5, got {:?}", other),
+        }
+    }
 
-            before: Vec::new(),
-            after: Vec::new(),
-        };
+    const PROFILES: &'static str = "version: \"%Y%m%d-1\"\n\
+                                    distribution: stable\n\
+                                    profile-from-branch: true\n\
+                                    profiles:\n\
+                                    \x20 unstable:\n\
+                                    \x20   distribution: unstable\n\
+                                    \x20 staging:\n\
+                                    \x20   distribution: staging\n";
 
-        for v in yaml["details"].as_vec().unwrap() {
-            let mut s = v.as_str().unwrap().to_string();
-            s = s.replace("{{ package }}", &it.package);
-            s = s.replace("{{ version }}", &it.version);
-            it.details.push(s);
+    // Profile `apply` picks and the distribution it ends up with.
+    fn applied(s: &str, profile: Option<&str>, branch: &Fn() -> Option<String>)
+        -> (Option<String>, String)
+    {
+        let yaml = YamlLoader::load_from_str(s).unwrap().swap_remove(0);
+        let (profile, yaml) = apply(yaml, profile, branch).unwrap();
+        assert_eq!(yaml["profiles"].is_badvalue(), profile.is_some());
+        (profile, yaml["distribution"].as_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn profile_from_branch() {
+        let unstable = || Some("debian/unstable".to_string());
+        assert_eq!(applied(PROFILES, None, &unstable),
+                   (Some("unstable".to_string()), "unstable".to_string()));
+
+        for branch in &["upstream/latest", "debian/bookworm/updates", "debian/sid", "master"] {
+            let branch = || Some(branch.to_string());
+            assert_eq!(applied(PROFILES, None, &branch), (None, "stable".to_string()));
         }
+        assert_eq!(applied(PROFILES, None, &|| None), (None, "stable".to_string()));
+    }
+
+    #[test]
+    fn branch_is_only_asked_for_when_needed() {
+        let never = || -> Option<String> { panic!("branch asked for") };
+        assert_eq!(applied(PROFILES, Some("staging"), &never),
+                   (Some("staging".to_string()), "staging".to_string()));
+
+        let s = PROFILES.replace("profile-from-branch: true", "profile-from-branch: false");
+        let yaml = YamlLoader::load_from_str(&s).unwrap().swap_remove(0);
+        assert_eq!(apply(yaml, None, &never).unwrap().0, None);
+    }
 
-        Ok(it)
+    #[test]
+    fn unknown_profile_lists_the_known_ones() {
+        let yaml = YamlLoader::load_from_str(PROFILES).unwrap().swap_remove(0);
+        match apply(yaml, Some("testing"), &|| None) {
+            Err(DCHFileError::UnknownProfile(ref name, ref known)) if name == "testing" => {
+                assert_eq!(*known, vec!["unstable", "staging"]);
+            },
+            Err(err) => panic!("expected UnknownProfile, got {:?}", err),
+            Ok(..) => panic!("expected UnknownProfile"),
+        }
+    }
+
+    #[test]
+    fn profile_cannot_set_top_level_keys() {
+        let path = write("not-in-profile", &[
+            ("Dchfile", "version: \"%Y%m%d-1\"\n\
+                         profiles:\n  staging:\n    urgency: low\n    \
+                         profile-from-branch: true\n"),
+        ]);
+        let errors = match DCHFile::new(&path, "dch", Some("staging"), &|| None) {
+            Ok(..) => panic!("expected an error"),
+            Err(errors) => errors,
+        };
+        assert_eq!(errors.len(), 1);
+        match errors[0] {
+            DCHFileError::NotInProfile(ref key, Some((5, 5))) if key == "profile-from-branch" => {},
+            ref other => panic!("expected NotInProfile at 5:5, got {:?}", other),
+        }
     }
 }
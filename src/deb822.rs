@@ -0,0 +1,217 @@
+use std::cmp;
+
+use bug::{Bug, Finder};
+use record::{Record, URGENCIES};
+
+
+/// Fields `parsechangelog` knows. All but "Closes" and the options of the
+/// newest entry ("Binary-Only", ...) are always there.
+pub const FIELDS: &'static [&'static str] = &[
+    "Source", "Version", "Distribution", "Urgency", "Maintainer", "Timestamp", "Date",
+    "Closes", "Binary-Only", "Changes",
+];
+
+/// Whether `name` is one of `FIELDS`, case-insensitively.
+pub fn is_field(name: &str) -> bool {
+    FIELDS.iter().any(|f| f.to_lowercase() == name.to_lowercase())
+}
+
+/// A single deb822 paragraph, fields kept in insertion order.
+pub struct Paragraph {
+    pub fields: Vec<(String, String)>,
+}
+
+impl Paragraph {
+    pub fn new() -> Paragraph {
+        Paragraph {
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Field names are case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|&&(ref n, _)| n.to_lowercase() == name.to_lowercase())
+            .map(|&(_, ref v)| &v[..])
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut s = String::new();
+
+        for &(ref name, ref value) in self.fields.iter() {
+            if value.starts_with("\n") {
+                s.push_str(&format!("{}:{}\n", name, value));
+            } else {
+                s.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+
+        s
+    }
+}
+
+// "binary-only" -> "Binary-Only"
+fn field_name(keyword: &str) -> String {
+    keyword
+        .split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().collect::<String>() + &chars.as_str().to_lowercase(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Entry text as it goes into "Changes:": header and body without the
+/// trailer, blank lines at the end dropped.
+fn changes(record: &Record) -> Vec<String> {
+    let text = record.to_string();
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        if line.starts_with(" --") {
+            break;
+        }
+        lines.push(line.trim_right().to_string());
+    }
+
+    while lines.last().map_or(false, |l| l.is_empty()) {
+        lines.pop();
+    }
+
+    lines
+}
+
+/// Builds the paragraph `dpkg-parsechangelog` prints for `records`
+/// (newest first). Source, Version, Distribution, Maintainer and Date come
+/// from the newest entry, Urgency is the highest one, Closes and Changes
+/// cover all entries. Like dpkg, an urgency that is not one of `URGENCIES`
+/// is left out of the ranking with a warning, warnings are returned along.
+pub fn parsechangelog(records: &[&Record]) -> (Paragraph, Vec<String>) {
+    let mut it = Paragraph::new();
+    let mut warnings: Vec<String> = Vec::new();
+
+    let first = match records.first() {
+        Some(first) => first,
+        None => return (it, warnings),
+    };
+
+    let mut rank = 0;
+    for record in records.iter() {
+        let urgency = record.urgency().to_lowercase();
+        match URGENCIES.iter().position(|u| *u == urgency) {
+            Some(position) => rank = cmp::max(rank, position),
+            None => warnings.push(format!("unknown urgency \"{}\" of {}",
+                                          record.urgency(), record.version)),
+        }
+    }
+    let urgency = URGENCIES[rank];
+
+    let mut closes: Vec<u32> = Vec::new();
+    let finder = Finder::new();
+    for record in records.iter() {
+        for bug in record.find_bugs(&finder) {
+            if let Bug::Debian(n) = bug {
+                closes.push(n);
+            }
+        }
+    }
+    closes.sort();
+    closes.dedup();
+
+    let mut changes_value = String::new();
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            changes_value.push_str("\n .");
+        }
+        for line in changes(record) {
+            if line.is_empty() {
+                changes_value.push_str("\n .");
+            } else {
+                changes_value.push_str(&format!("\n {}", line));
+            }
+        }
+    }
+
+    it.push("Source", &first.package);
+    it.push("Version", &first.version.to_string());
+    it.push("Distribution", &first.distribution());
+    it.push("Urgency", urgency);
+    it.push("Maintainer", &format!("{} <{}>", first.mantainer_name, first.mantainer_email));
+    it.push("Timestamp", &first.date.timestamp().to_string());
+    it.push("Date", &first.date.format("%a, %d %b %Y %H:%M:%S %z").to_string());
+    if !closes.is_empty() {
+        let closes: Vec<String> = closes.iter().map(|n| n.to_string()).collect();
+        it.push("Closes", &closes.join(" "));
+    }
+    for &(ref keyword, ref value) in first.options.iter() {
+        if keyword.to_lowercase() != "urgency" {
+            it.push(&field_name(keyword), value);
+        }
+    }
+    it.push("Changes", &changes_value);
+
+    (it, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use changelog::Changelog;
+    use super::{is_field, parsechangelog};
+
+    fn changelog(urgencies: &[&str]) -> Changelog {
+        let mut s = String::new();
+        for (i, urgency) in urgencies.iter().enumerate() {
+            s.push_str(&format!("foo (1.{}) unstable; urgency={}\n\n  * Change.\n\n \
+                                 -- Carol <carol@example.com>  Mon, 01 Jan 2024 10:00:00 +0000\n\n",
+                                urgencies.len() - i, urgency));
+        }
+        s.parse::<Changelog>().unwrap()
+    }
+
+    #[test]
+    fn urgency_is_the_highest_one() {
+        let changelog = changelog(&["low", "HIGH (security)", "medium"]);
+        let records: Vec<_> = changelog.records.iter().collect();
+        let (paragraph, warnings) = parsechangelog(&records);
+        assert_eq!(paragraph.get("urgency"), Some("high"));
+        assert_eq!(paragraph.get("Version"), Some("1.3"));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn emergency_ranks_highest() {
+        let changelog = changelog(&["critical", "emergency", "high"]);
+        let records: Vec<_> = changelog.records.iter().collect();
+        assert_eq!(parsechangelog(&records).0.get("Urgency"), Some("emergency"));
+    }
+
+    #[test]
+    fn unknown_urgency_is_a_warning() {
+        let changelog = changelog(&["whenever", "medium"]);
+        let records: Vec<_> = changelog.records.iter().collect();
+        let (paragraph, warnings) = parsechangelog(&records);
+        assert_eq!(paragraph.get("Urgency"), Some("medium"));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("whenever"));
+    }
+
+    #[test]
+    fn closes_is_known_but_may_be_missing() {
+        let changelog = changelog(&["low"]);
+        let records: Vec<_> = changelog.records.iter().collect();
+        let (paragraph, _) = parsechangelog(&records);
+        assert_eq!(paragraph.get("Closes"), None);
+        assert!(is_field("closes"));
+        assert!(is_field("Binary-Only"));
+        assert!(!is_field("Closed"));
+    }
+}
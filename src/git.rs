@@ -0,0 +1,346 @@
+use std::fmt;
+use std::error;
+use std::io;
+use std::fs;
+use std::process::Command;
+use std::string::FromUtf8Error;
+
+use regex::Regex;
+
+use mailmap::Mailmap;
+use record::MantainerDetails;
+use version::DebianVersion;
+
+
+const FIELD_SEPARATOR: char = '\x1f';
+const RECORD_SEPARATOR: char = '\x1e';
+
+
+#[derive(Debug)]
+pub enum GitError {
+    IO(io::Error),
+    UTF8(FromUtf8Error),
+    Command(String, String),
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GitError::IO(ref err)   => write!(f, "failed to run git: {}", err),
+            GitError::UTF8(ref err) => write!(f, "failed to convert git output \
+                                                 to utf8: {}", err),
+            GitError::Command(ref args, ref stderr) => write!(f, "\"git {}\" failed: {}",
+                                                              args, stderr.trim()),
+        }
+    }
+}
+
+impl From<io::Error> for GitError {
+    fn from(err: io::Error) -> GitError {
+        GitError::IO(err)
+    }
+}
+
+impl From<FromUtf8Error> for GitError {
+    fn from(err: FromUtf8Error) -> GitError {
+        GitError::UTF8(err)
+    }
+}
+
+impl error::Error for GitError {
+    fn description(&self) -> &str {
+        match *self {
+            GitError::IO(ref err) => err.description(),
+            GitError::UTF8(ref err) => err.description(),
+            GitError::Command(..) => "git command failed",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            GitError::IO(ref err) => Some(err),
+            GitError::UTF8(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub sha: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// A local git repository. Only local commands are run, nothing is fetched.
+pub struct Repository {
+    pub path: String,
+}
+
+impl Repository {
+    pub fn new(path: &str) -> Repository {
+        Repository {
+            path: path.to_string(),
+        }
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, GitError> {
+        let output = try!(Command::new("git")
+            .arg("-C")
+            .arg(&self.path)
+            .args(args)
+            .output());
+
+        if !output.status.success() {
+            return Err(GitError::Command(
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+
+        Ok(try!(String::from_utf8(output.stdout)))
+    }
+
+    fn resolve(&self, rev: &str) -> Option<String> {
+        let rev = format!("{}^{{commit}}", rev);
+        match self.git(&["rev-parse", "--verify", "--quiet", &rev]) {
+            Ok(sha) => Some(sha.trim().to_string()),
+            Err(..) => None,
+        }
+    }
+
+    /// Abbreviated name of the HEAD commit.
+    pub fn head(&self) -> Option<String> {
+        match self.git(&["rev-parse", "--short", "HEAD"]) {
+            Ok(sha) => Some(sha.trim().to_string()),
+            Err(..) => None,
+        }
+    }
+
+    /// Checked out branch, `None` on a detached HEAD.
+    pub fn branch(&self) -> Option<String> {
+        match self.git(&["symbolic-ref", "--quiet", "--short", "HEAD"]) {
+            Ok(branch) => Some(branch.trim().to_string()),
+            Err(..) => None,
+        }
+    }
+
+    fn tag(&self, tags: &[String]) -> Option<String> {
+        tags.iter().filter_map(|tag| self.resolve(&format!("refs/tags/{}", tag))).next()
+    }
+
+    /// Commit the version was released from: a tag named after the version
+    /// (DEP-14 "debian/1%1.2-3" or "1.2-3"), else the commit that added the
+    /// version to `changelog`, else for the first revision of an upstream
+    /// version (or a native one) an upstream tag ("v1.2", "1.2",
+    /// "upstream/1.2"). `changelog` is relative to the working directory.
+    pub fn release(&self, version: &DebianVersion, changelog: &str) -> Option<String> {
+        let full = version.to_string();
+        let dep14 = full.replace(':', "%").replace('~', "_");
+        let upstream = version.upstream();
+
+        if let Some(sha) = self.tag(&[format!("debian/{}", dep14), full.to_string()]) {
+            return Some(sha);
+        }
+
+        // git runs in the repository, so the path must not be relative
+        if let Ok(path) = fs::canonicalize(changelog) {
+            let pickaxe = format!("-S({})", full);
+            let path = path.to_string_lossy();
+            match self.git(&["log", "-n", "1", "--format=%H", &pickaxe, "--", &path]) {
+                Ok(ref sha) if !sha.trim().is_empty() => return Some(sha.trim().to_string()),
+                _ => {},
+            }
+        }
+
+        // later revisions start after the previous revision, not upstream
+        match version.revision() {
+            None | Some("1") => self.tag(&[
+                format!("v{}", upstream),
+                upstream.to_string(),
+                format!("upstream/{}", upstream.replace('~', "_")),
+            ]),
+            Some(..) => None,
+        }
+    }
+
+    /// Commits reachable from HEAD but not from `since`, oldest first,
+    /// merges left out.
+    pub fn log(&self, since: Option<&str>) -> Result<Vec<Commit>, GitError> {
+        let format = format!("--format=%H{0}%an{0}%ae{0}%s{0}%b{1}",
+            FIELD_SEPARATOR, RECORD_SEPARATOR);
+        let range = match since {
+            Some(since) => format!("{}..HEAD", since),
+            None => "HEAD".to_string(),
+        };
+
+        let output = try!(self.git(&["log", "--no-merges", "--reverse", &format, &range]));
+
+        let mut commits: Vec<Commit> = Vec::new();
+        for record in output.split(RECORD_SEPARATOR) {
+            let fields: Vec<&str> = record.trim_left_matches('\n').split(FIELD_SEPARATOR).collect();
+            if fields.len() < 5 {
+                continue;
+            }
+
+            commits.push(Commit {
+                sha: fields[0].to_string(),
+                author_name: fields[1].to_string(),
+                author_email: fields[2].to_string(),
+                subject: fields[3].to_string(),
+                body: fields[4].trim().to_string(),
+            });
+        }
+
+        Ok(commits)
+    }
+}
+
+/// Suite a DEP-14 packaging branch is for: "debian/unstable" is for
+/// "unstable", "ubuntu/jammy" for "jammy". `None` for upstream branches
+/// and branches not named after that scheme.
+pub fn suite(branch: &str) -> Option<&str> {
+    let mut parts = branch.splitn(2, '/');
+    match (parts.next(), parts.next()) {
+        (Some("upstream"), _) => None,
+        (Some(vendor), Some(suite)) if !vendor.is_empty() && !suite.is_empty()
+            && !suite.contains('/') => Some(suite),
+        _ => None,
+    }
+}
+
+/// Drops commits whose subject matches any of the `ignore` expressions.
+/// Invalid expressions are reported as `Err` with the expression.
+pub fn filter(commits: Vec<Commit>, ignore: &[String]) -> Result<Vec<Commit>, String> {
+    let mut res: Vec<Regex> = Vec::new();
+    for pattern in ignore.iter() {
+        match Regex::new(pattern) {
+            Ok(re) => res.push(re),
+            Err(err) => return Err(format!("{}: {}", pattern, err)),
+        }
+    }
+
+    Ok(commits
+        .into_iter()
+        .filter(|c| !res.iter().any(|re| re.is_match(&c.subject)))
+        .collect())
+}
+
+/// One `[ Name ]` block per author, in order of their first commit, with
+/// names normalised through `mailmap`. The uploader's own changes come
+/// first and stay unnamed unless somebody else contributed too.
+pub fn authors(commits: Vec<Commit>, mailmap: &Mailmap, uploader_name: &str, uploader_email: &str)
+    -> Vec<MantainerDetails>
+{
+    let (uploader_name, uploader_email) = mailmap.resolve(uploader_name, uploader_email);
+    let mut blocks: Vec<MantainerDetails> = Vec::new();
+    let mut uploader: Option<usize> = None;
+
+    for commit in commits {
+        let (name, email) = mailmap.resolve(&commit.author_name, &commit.author_email);
+        let is_uploader = name == uploader_name
+            || email.to_lowercase() == uploader_email.to_lowercase();
+        let name = if is_uploader { uploader_name.to_string() } else { name };
+
+        match blocks.iter().position(|b| b.mantainer == name) {
+            Some(i) => blocks[i].details.push(commit.subject),
+            None => {
+                if is_uploader {
+                    uploader = Some(blocks.len());
+                }
+                let mut block = MantainerDetails::with_details(vec![commit.subject]);
+                block.mantainer = name;
+                blocks.push(block);
+            },
+        }
+    }
+
+    if let Some(i) = uploader {
+        let block = blocks.remove(i);
+        blocks.insert(0, block);
+        if blocks.len() == 1 {
+            blocks[0].mantainer = String::new();
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+
+    use version::DebianVersion;
+    use super::{suite, Repository};
+
+    #[test]
+    fn suite_of_packaging_branches() {
+        assert_eq!(suite("debian/unstable"), Some("unstable"));
+        assert_eq!(suite("debian/experimental"), Some("experimental"));
+        assert_eq!(suite("ubuntu/jammy"), Some("jammy"));
+    }
+
+    #[test]
+    fn no_suite_for_other_branches() {
+        assert_eq!(suite("upstream/latest"), None);
+        assert_eq!(suite("upstream/1.2"), None);
+        assert_eq!(suite("master"), None);
+        assert_eq!(suite("debian/"), None);
+        assert_eq!(suite("/unstable"), None);
+        assert_eq!(suite("debian/bookworm/updates"), None);
+        assert_eq!(suite("feature/debian/unstable"), None);
+    }
+
+    // A repository whose changelog got 1.2-1 (tagged "v1.2") and then 1.2-2,
+    // each in a commit of its own; returns it and the commits, oldest first.
+    fn repository(test: &str) -> (Repository, String, Vec<String>) {
+        let dir = env::temp_dir().join(format!("dch-git-{}", test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("debian")).unwrap();
+        let repository = Repository::new(&dir.to_string_lossy());
+        let changelog = dir.join("debian").join("changelog").to_string_lossy().into_owned();
+
+        repository.git(&["init", "-q"]).unwrap();
+        let mut commits: Vec<String> = Vec::new();
+        let mut text = String::new();
+        for version in &["1.2-1", "1.2-2"] {
+            text = format!("foo ({}) unstable; urgency=low\n{}", version, text);
+            File::create(&changelog).unwrap().write_all(text.as_bytes()).unwrap();
+            repository.git(&["add", "-A"]).unwrap();
+            repository.git(&["-c", "user.name=Carol", "-c", "user.email=carol@example.com",
+                             "commit", "-q", "-m", version]).unwrap();
+            commits.push(repository.resolve("HEAD").unwrap());
+        }
+        repository.git(&["tag", "v1.2", &commits[0]]).unwrap();
+
+        (repository, changelog, commits)
+    }
+
+    #[test]
+    fn release_of_a_later_revision_is_where_it_was_added() {
+        let (repository, changelog, commits) = repository("later-revision");
+        let version = |s: &str| s.parse::<DebianVersion>().unwrap();
+
+        assert_eq!(repository.release(&version("1.2-2"), &changelog), Some(commits[1].clone()));
+        assert_eq!(repository.release(&version("1.2-3"), &changelog), None);
+        assert_eq!(repository.release(&version("1.2-1"), &changelog), Some(commits[0].clone()));
+
+        repository.git(&["tag", "debian/1.2-2", &commits[0]]).unwrap();
+        assert_eq!(repository.release(&version("1.2-2"), &changelog), Some(commits[0].clone()));
+    }
+
+    #[test]
+    fn release_falls_back_to_upstream_tags_for_first_revisions() {
+        let (repository, changelog, commits) = repository("upstream-tag");
+        let version = |s: &str| s.parse::<DebianVersion>().unwrap();
+        let missing = changelog.replace("changelog", "gone");
+
+        assert_eq!(repository.release(&version("1.2-1"), &missing), Some(commits[0].clone()));
+        assert_eq!(repository.release(&version("1.2"), &missing), Some(commits[0].clone()));
+        assert_eq!(repository.release(&version("1.2-2"), &missing), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::process::Command;
+
+
+/// Environment hooks run with; the old version is empty for the first
+/// entry of a changelog. Hooks run in the package directory, so the
+/// changelog path should be absolute.
+pub struct Env {
+    pub package: String,
+    pub old_version: Option<String>,
+    pub new_version: String,
+    pub changelog: String,
+    pub distribution: String,
+}
+
+impl Env {
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("DCH_PACKAGE", self.package.to_string()),
+            ("DCH_OLD_VERSION", self.old_version.clone().unwrap_or(String::new())),
+            ("DCH_NEW_VERSION", self.new_version.to_string()),
+            ("DCH_CHANGELOG", self.changelog.to_string()),
+            ("DCH_DISTRIBUTION", self.distribution.to_string()),
+        ]
+    }
+}
+
+/// One hook run with its captured output, kept for the run report.
+pub struct Run {
+    pub package: String,
+    /// "before" or "after"
+    pub stage: &'static str,
+    pub argv: Vec<String>,
+    /// exit code, `None` if the hook could not be started or was killed
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// why the hook could not be started
+    pub error: Option<String>,
+}
+
+impl Run {
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut s = String::new();
+
+        let result = match (self.status, self.error.as_ref()) {
+            (_, Some(err)) => format!("failed to start: {}", err),
+            (Some(code), None) => format!("exit {}", code),
+            (None, None) => "killed".to_string(),
+        };
+        s.push_str(&format!("{} {} \"{}\": {}\n",
+            self.package, self.stage, self.argv.join(" "), result));
+
+        for line in self.stdout.lines() {
+            s.push_str(&format!("\t{}\n", line));
+        }
+        for line in self.stderr.lines() {
+            s.push_str(&format!("\t! {}\n", line));
+        }
+
+        s
+    }
+}
+
+/// Runs `argv` in `dir` without a shell and waits for it.
+pub fn run(package: &str, stage: &'static str, argv: &[String], env: &Env, dir: &str) -> Run {
+    let mut it = Run {
+        package: package.to_string(),
+        stage: stage,
+        argv: argv.to_vec(),
+        status: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: None,
+    };
+
+    let (program, args) = match argv.split_first() {
+        Some(split) => split,
+        None => {
+            it.error = Some("empty command".to_string());
+            return it;
+        }
+    };
+
+    let mut command = Command::new(program);
+    command.args(args).current_dir(dir);
+    for (key, value) in env.vars() {
+        command.env(key, value);
+    }
+
+    match command.output() {
+        Ok(output) => {
+            it.status = output.status.code();
+            it.stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            it.stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        },
+        Err(err) => it.error = Some(err.to_string()),
+    }
+
+    it
+}
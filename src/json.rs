@@ -0,0 +1,221 @@
+use std::fmt::Display;
+
+use chrono::DateTime;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
+use serde_json;
+
+use changelog::Changelog;
+use cst;
+use record::{Record, MantainerDetails};
+use version::DebianVersion;
+
+
+#[derive(Serialize, Deserialize)]
+struct VersionJson {
+    full: String,
+    epoch: u32,
+    upstream: String,
+    revision: Option<String>,
+    native: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MantainerJson {
+    name: String,
+    email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DateJson {
+    rfc2822: String,
+    timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordJson {
+    package: String,
+    version: VersionJson,
+    distributions: Vec<String>,
+    options: Vec<(String, String)>,
+    urgency: String,
+    #[serde(default)]
+    closes: Vec<u32>,
+    #[serde(default)]
+    launchpad: Vec<u32>,
+    mantainer_details: Vec<MantainerDetails>,
+    mantainer: MantainerJson,
+    date: DateJson,
+    /// original text of the entry, used to write untouched entries back
+    /// byte for byte after an import
+    #[serde(default)]
+    raw: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChangelogJson {
+    #[serde(default)]
+    leading: String,
+    records: Vec<RecordJson>,
+}
+
+impl<'a> From<&'a Record> for RecordJson {
+    fn from(record: &'a Record) -> RecordJson {
+        RecordJson {
+            package: record.package.to_string(),
+            version: VersionJson {
+                full: record.version.to_string(),
+                epoch: record.version.epoch(),
+                upstream: record.version.upstream().to_string(),
+                revision: record.version.revision().map(|r| r.to_string()),
+                native: record.version.is_native(),
+            },
+            distributions: record.distributions.to_vec(),
+            options: record.options.to_vec(),
+            urgency: record.urgency().to_string(),
+            closes: record.closes(),
+            launchpad: record.launchpad_bugs(),
+            mantainer_details: record.mantainer_details.to_vec(),
+            mantainer: MantainerJson {
+                name: record.mantainer_name.to_string(),
+                email: record.mantainer_email.to_string(),
+            },
+            date: DateJson {
+                rfc2822: record.date.format("%a, %d %b %Y %H:%M:%S %z").to_string(),
+                timestamp: record.date.timestamp(),
+            },
+            raw: record.source.as_ref().map(|s| s.to_string()),
+        }
+    }
+}
+
+fn invalid<E: Error, T: Display>(what: &str, value: &str, err: T) -> E {
+    E::custom(format!("invalid {} \"{}\": {}", what, value, err))
+}
+
+impl RecordJson {
+    // Version components, urgency and bugs are derived data, only the full
+    // version string and the options are read back.
+    fn into_record<E: Error>(self) -> Result<Record, E> {
+        let mut record = Record::new();
+
+        record.package = self.package;
+        record.version = match self.version.full.parse::<DebianVersion>() {
+            Ok(version) => version,
+            Err(err) => return Err(invalid("version", &self.version.full, err)),
+        };
+        record.distributions = self.distributions;
+        record.options = self.options;
+        record.mantainer_details = self.mantainer_details;
+        record.mantainer_name = self.mantainer.name;
+        record.mantainer_email = self.mantainer.email;
+        record.date = match DateTime::parse_from_rfc2822(&self.date.rfc2822) {
+            Ok(date) => date,
+            Err(err) => return Err(invalid("date", &self.date.rfc2822, err)),
+        };
+
+        if let Some(raw) = self.raw {
+            match raw.parse::<Changelog>() {
+                Ok(mut changelog) => if !changelog.records.is_empty() {
+                    record.source = changelog.records.remove(0).source;
+                },
+                Err(diagnostics) => {
+                    let first = diagnostics.first().map_or(String::new(), |d| d.to_string());
+                    return Err(invalid("raw entry", raw.lines().next().unwrap_or(""), first));
+                },
+            }
+        }
+
+        Ok(record)
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordJson::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Record, D::Error> {
+        let it = try!(RecordJson::deserialize(deserializer));
+        it.into_record()
+    }
+}
+
+impl Serialize for Changelog {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let records: Vec<&Record> = self.records.iter().collect();
+        changelog_json(self, &records).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Changelog {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Changelog, D::Error> {
+        let it = try!(ChangelogJson::deserialize(deserializer));
+
+        let mut changelog = Changelog::new();
+        changelog.leading = cst::lines(&it.leading);
+
+        for record in it.records {
+            changelog.records.push(try!(record.into_record()));
+        }
+
+        Ok(changelog)
+    }
+}
+
+fn changelog_json(changelog: &Changelog, records: &[&Record]) -> ChangelogJson {
+    let mut leading = String::new();
+    for line in changelog.leading.iter() {
+        leading.push_str(&line.text);
+    }
+
+    ChangelogJson {
+        leading: leading,
+        records: records.iter().map(|r| RecordJson::from(*r)).collect(),
+    }
+}
+
+/// Exports `records` (usually a selection of `changelog`) as pretty JSON.
+pub fn export(changelog: &Changelog, records: &[&Record]) -> String {
+    serde_json::to_string_pretty(&changelog_json(changelog, records)).unwrap()
+}
+
+pub fn import(s: &str) -> Result<Changelog, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use changelog::Changelog;
+    use cst;
+    use super::{export, import};
+
+    const LEADING: &'static str = "Free text before the first entry.\n\n";
+
+    const ENTRY: &'static str = "\
+foo (1.0-1) unstable; urgency=medium
+
+  * Initial release. Closes: #123456
+
+ -- Carol <carol@example.com>  Mon, 01 Jan 2024 10:00:00 +0000
+";
+
+    #[test]
+    fn export_import_round_trip() {
+        let mut changelog = Changelog::new();
+        changelog.read(ENTRY.as_bytes(), "<string>").unwrap();
+        changelog.leading = cst::lines(LEADING);
+        assert_eq!(changelog.to_string(), format!("{}{}", LEADING, ENTRY));
+
+        let records: Vec<_> = changelog.records.iter().collect();
+        let imported = import(&export(&changelog, &records)).unwrap();
+
+        let leading: Vec<&str> = imported.leading.iter().map(|l| &l.text[..]).collect();
+        assert_eq!(leading, vec!["Free text before the first entry.\n", "\n"]);
+        assert_eq!(imported.records.len(), 1);
+        assert_eq!(imported.records[0].closes(), vec![123456]);
+        assert_eq!(imported.to_string(), format!("{}{}", LEADING, ENTRY));
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+
+use record::{Record, MantainerDetails};
+use version::{DebianVersion, VersionError};
+
+
+/// One "### Added" (or Changed, Fixed, Security, ...) block of a release.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub kind: String,
+    pub items: Vec<String>,
+}
+
+/// One "## [1.2.0] - 2026-01-01" block of a Keep a Changelog file.
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub version: String,
+    /// "YYYY-MM-DD" as written, if any
+    pub date: Option<String>,
+    pub sections: Vec<Section>,
+}
+
+impl Release {
+    pub fn is_unreleased(&self) -> bool {
+        self.version.to_lowercase() == "unreleased"
+    }
+
+    /// Every item becomes a bullet prefixed with its section:
+    /// "Fixed: crash on start".
+    pub fn details(&self) -> Vec<String> {
+        let mut details: Vec<String> = Vec::new();
+
+        for section in self.sections.iter() {
+            for item in section.items.iter() {
+                details.push(format!("{}: {}", section.kind, item));
+            }
+        }
+
+        details
+    }
+
+    pub fn mantainer_details(&self) -> Vec<MantainerDetails> {
+        vec![MantainerDetails::with_details(self.details())]
+    }
+
+    /// Release date at midnight UTC.
+    pub fn datetime(&self) -> Option<DateTime<FixedOffset>> {
+        match self.date {
+            Some(ref date) => DateTime::parse_from_rfc3339(&format!("{}T00:00:00+00:00", date)).ok(),
+            None => None,
+        }
+    }
+
+    /// Debian version for the release with the given debian revision.
+    /// A semver pre-release ("1.2.0-rc.1") maps to "1.2.0~rc.1" so that it
+    /// sorts before the final release.
+    pub fn debian_version(&self, revision: &str) -> Result<DebianVersion, VersionError> {
+        let upstream = self.version.trim_left_matches('v').replacen("-", "~", 1);
+        DebianVersion::new(0, &upstream, Some(revision))
+    }
+}
+
+/// Parses a Keep a Changelog markdown document. Releases are returned in
+/// document order, which is newest first.
+pub fn parse(s: &str) -> Vec<Release> {
+    let re_release = Regex::new(r"(?x)
+        ^\#\#\s+
+            \[?(?P<version>[^\]\s]+)\]?                 # [1.2.0] or 1.2.0
+        (?:\s+-\s+
+            (?P<date>\d{4}-\d{2}-\d{2}))?               # - 2026-01-01
+    ").unwrap();
+    let re_section = Regex::new(r"^\#\#\#\s+(?P<kind>.+?)\s*$").unwrap();
+    let re_item = Regex::new(r"^[-*+]\s+(?P<item>.*)$").unwrap();
+
+    let mut releases: Vec<Release> = Vec::new();
+
+    for line in s.lines() {
+        if let Some(cap) = re_release.captures(line) {
+            releases.push(Release {
+                version: cap.name("version").unwrap().to_string(),
+                date: cap.name("date").map(|d| d.to_string()),
+                sections: Vec::new(),
+            });
+            continue;
+        }
+
+        let release = match releases.iter_mut().last() {
+            Some(release) => release,
+            None => continue, // title and introduction
+        };
+
+        if line.starts_with("# ") || line.starts_with("[") {
+            continue; // link reference definitions at the bottom
+        }
+
+        if let Some(cap) = re_section.captures(line) {
+            release.sections.push(Section {
+                kind: cap.name("kind").unwrap().to_string(),
+                items: Vec::new(),
+            });
+            continue;
+        }
+
+        let section = match release.sections.iter_mut().last() {
+            Some(section) => section,
+            None => continue,
+        };
+
+        if let Some(cap) = re_item.captures(line) {
+            section.items.push(cap.name("item").unwrap().trim().to_string());
+        } else if !line.trim().is_empty() && line.starts_with(" ") {
+            // continuation of the previous item, kept as a changelog
+            // continuation line
+            if let Some(item) = section.items.iter_mut().last() {
+                item.push_str("\n    ");
+                item.push_str(line.trim());
+            }
+        }
+    }
+
+    releases
+}
+
+pub fn from(path: &str) -> io::Result<Vec<Release>> {
+    let mut s = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut s));
+    Ok(parse(&s))
+}
+
+/// The release named `version`, or the newest one that is not
+/// "Unreleased" if `version` is `None`.
+pub fn release<'a>(releases: &'a [Release], version: Option<&str>) -> Option<&'a Release> {
+    match version {
+        Some(version) => releases
+            .iter()
+            .find(|r| r.version.trim_left_matches('v') == version.trim_left_matches('v')),
+        None => releases.iter().find(|r| !r.is_unreleased()),
+    }
+}
+
+/// Builds one record per released version, newest first, to seed a
+/// debian/changelog from the whole markdown history. Fields that the
+/// markdown does not carry are copied from `template`.
+pub fn records(releases: &[Release], template: &Record) -> Result<Vec<Record>, VersionError> {
+    let mut records: Vec<Record> = Vec::new();
+
+    for release in releases.iter().filter(|r| !r.is_unreleased()) {
+        let mut record = template.copy();
+        record.version = try!(release.debian_version("1"));
+        record.mantainer_details = release.mantainer_details();
+        if let Some(date) = release.datetime() {
+            record.date = date;
+        }
+        records.push(record);
+    }
+
+    Ok(records)
+}
@@ -3,8 +3,35 @@ extern crate clap;
 extern crate regex;
 extern crate chrono;
 extern crate yaml_rust;
+#[cfg(feature = "json")]
+extern crate serde;
+#[cfg(feature = "json")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "json")]
+extern crate serde_json;
 
+pub mod backport;
+pub mod bug;
 pub mod changelog;
+pub mod cst;
 pub mod record;
+pub mod render;
+pub mod select;
+pub mod series;
+pub mod strategy;
+pub mod template;
+pub mod upload;
 pub mod config;
+pub mod conventional;
 pub mod dchfile;
+pub mod deb822;
+pub mod git;
+pub mod hook;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod keepachangelog;
+pub mod lint;
+pub mod mailmap;
+pub mod marks;
+pub mod version;
@@ -0,0 +1,553 @@
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+use yaml_rust::Yaml;
+
+use backport::RELEASES;
+use bug::Finder;
+use changelog::Changelog;
+use cst::{Kind, Line};
+use record::{Record, URGENCIES};
+
+
+// besides the release codenames of `backport::RELEASES`
+const DISTRIBUTIONS: &'static [&'static str] = &[
+    "unstable", "experimental", "testing", "stable", "oldstable", "oldoldstable",
+    "sid", "UNRELEASED",
+];
+
+// "bookworm-security", "stable-backports", "trixie-proposed-updates", ...
+const DISTRIBUTION_SUFFIXES: &'static [&'static str] = &[
+    "-security", "-backports", "-backports-sloppy", "-proposed-updates", "-updates",
+];
+
+const MAX_LINE_LENGTH: usize = 80;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn parse(s: &str) -> Option<Severity> {
+        match s {
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Lint rules. Ids are stable: never renumber, only append.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnknownUrgency,
+    UnknownDistribution,
+    MalformedEmail,
+    TrailerSpacing,
+    DateBackwards,
+    LineTooLong,
+    Tab,
+    TrailingWhitespace,
+    DuplicateVersion,
+    EmptyEntry,
+    DuplicateCloses,
+    UpstreamStart,
+}
+
+pub const RULES: &'static [Rule] = &[
+    Rule::UnknownUrgency,
+    Rule::UnknownDistribution,
+    Rule::MalformedEmail,
+    Rule::TrailerSpacing,
+    Rule::DateBackwards,
+    Rule::LineTooLong,
+    Rule::Tab,
+    Rule::TrailingWhitespace,
+    Rule::DuplicateVersion,
+    Rule::EmptyEntry,
+    Rule::DuplicateCloses,
+    Rule::UpstreamStart,
+];
+
+impl Rule {
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Rule::UnknownUrgency      => "DCH001",
+            Rule::UnknownDistribution => "DCH002",
+            Rule::MalformedEmail      => "DCH003",
+            Rule::TrailerSpacing      => "DCH004",
+            Rule::DateBackwards       => "DCH005",
+            Rule::LineTooLong         => "DCH006",
+            Rule::Tab                 => "DCH007",
+            Rule::TrailingWhitespace  => "DCH008",
+            Rule::DuplicateVersion    => "DCH009",
+            Rule::EmptyEntry          => "DCH010",
+            Rule::DuplicateCloses     => "DCH011",
+            Rule::UpstreamStart       => "DCH012",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Rule::UnknownUrgency      => "unknown-urgency",
+            Rule::UnknownDistribution => "unknown-distribution",
+            Rule::MalformedEmail      => "malformed-email",
+            Rule::TrailerSpacing      => "trailer-spacing",
+            Rule::DateBackwards       => "date-backwards",
+            Rule::LineTooLong         => "line-too-long",
+            Rule::Tab                 => "tab",
+            Rule::TrailingWhitespace  => "trailing-whitespace",
+            Rule::DuplicateVersion    => "duplicate-version",
+            Rule::EmptyEntry          => "empty-entry",
+            Rule::DuplicateCloses     => "duplicate-closes",
+            Rule::UpstreamStart       => "upstream-start",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match *self {
+            Rule::UnknownUrgency      => Severity::Error,
+            Rule::UnknownDistribution => Severity::Warning,
+            Rule::MalformedEmail      => Severity::Error,
+            Rule::TrailerSpacing      => Severity::Error,
+            Rule::DateBackwards       => Severity::Warning,
+            Rule::LineTooLong         => Severity::Warning,
+            Rule::Tab                 => Severity::Warning,
+            Rule::TrailingWhitespace  => Severity::Warning,
+            Rule::DuplicateVersion    => Severity::Error,
+            Rule::EmptyEntry          => Severity::Error,
+            Rule::DuplicateCloses     => Severity::Warning,
+            Rule::UpstreamStart       => Severity::Warning,
+        }
+    }
+
+    /// Accepts either the id ("DCH006") or the name ("line-too-long").
+    pub fn find(s: &str) -> Option<Rule> {
+        RULES.iter().find(|r| r.id() == s || r.name() == s).cloned()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// 1-based, in the changelog as it is written
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} {} [{}]: {}",
+            self.line, self.severity, self.rule.id(), self.rule.name(), self.message)
+    }
+}
+
+/// Per package lint settings, the "lint" key of a package in .dchrc:
+///
+/// ```yaml
+/// lint:
+///   enforce: true            # refuse to write a bump that has errors
+///   disable: [DCH006, tab]
+///   severity:
+///     unknown-distribution: error
+///   distributions: [staging]
+///   max-line-length: 100
+/// ```
+pub struct LintConfig {
+    pub enforce: bool,
+    pub disable: HashSet<Rule>,
+    pub severity: HashMap<Rule, Severity>,
+    pub distributions: Vec<String>,
+    pub max_line_length: usize,
+}
+
+impl LintConfig {
+    pub fn new() -> LintConfig {
+        LintConfig {
+            enforce: false,
+            disable: HashSet::new(),
+            severity: HashMap::new(),
+            distributions: Vec::new(),
+            max_line_length: MAX_LINE_LENGTH,
+        }
+    }
+
+    /// Unknown rules and severities are reported as `Err` with a message.
+    pub fn from_yaml(yaml: &Yaml) -> Result<LintConfig, String> {
+        let mut it = LintConfig::new();
+
+        it.enforce = yaml["enforce"].as_bool().unwrap_or(false);
+        it.max_line_length = yaml["max-line-length"]
+            .as_i64()
+            .map_or(MAX_LINE_LENGTH, |n| n as usize);
+
+        if let Some(rules) = yaml["disable"].as_vec() {
+            for rule in rules.iter() {
+                it.disable.insert(try!(rule_of(rule)));
+            }
+        }
+
+        if let Some(severities) = yaml["severity"].as_hash() {
+            for (rule, severity) in severities {
+                let rule = try!(rule_of(rule));
+                let severity = match severity.as_str().and_then(Severity::parse) {
+                    Some(severity) => severity,
+                    None => return Err(format!("unknown severity {:?} for {}, expected \
+                                                \"warning\" or \"error\"", severity, rule.id())),
+                };
+                it.severity.insert(rule, severity);
+            }
+        }
+
+        if let Some(distributions) = yaml["distributions"].as_vec() {
+            for distribution in distributions.iter().filter_map(|d| d.as_str()) {
+                it.distributions.push(distribution.to_string());
+            }
+        }
+
+        Ok(it)
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut disable: Vec<&str> = self.disable.iter().map(|r| r.id()).collect();
+        disable.sort();
+
+        format!("enforce: {}, disable: [{}], max-line-length: {}",
+            self.enforce, disable.join(", "), self.max_line_length)
+    }
+
+    fn is_known_distribution(&self, distribution: &str) -> bool {
+        if self.distributions.iter().any(|d| d == distribution) {
+            return true;
+        }
+
+        let base = DISTRIBUTION_SUFFIXES
+            .iter()
+            .find(|suffix| distribution.ends_with(*suffix))
+            .map_or(distribution, |suffix| &distribution[..distribution.len() - suffix.len()]);
+
+        DISTRIBUTIONS.iter().any(|d| *d == base)
+            || RELEASES.iter().any(|&(codename, _)| codename == base)
+    }
+}
+
+fn rule_of(yaml: &Yaml) -> Result<Rule, String> {
+    match yaml.as_str().and_then(Rule::find) {
+        Some(rule) => Ok(rule),
+        None => Err(format!("unknown lint rule {:?}", yaml.as_str().unwrap_or(""))),
+    }
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, rule: Rule, line: usize, message: String) {
+        if self.config.disable.contains(&rule) {
+            return;
+        }
+
+        let severity = self.config.severity.get(&rule).cloned().unwrap_or(rule.severity());
+        self.findings.push(Finding {
+            rule: rule,
+            severity: severity,
+            line: line,
+            message: message,
+        });
+    }
+
+    fn lines(&mut self, lines: &[Line]) {
+        for line in lines.iter() {
+            let content = line.content();
+
+            let length = content.chars().count();
+            if length > self.config.max_line_length {
+                let message = format!("line is {} characters long, maximum is {}",
+                    length, self.config.max_line_length);
+                self.report(Rule::LineTooLong, line.number, message);
+            }
+
+            if content.contains('\t') {
+                self.report(Rule::Tab, line.number, "line contains a tab".to_string());
+            }
+
+            if content.trim_right() != content {
+                self.report(Rule::TrailingWhitespace, line.number,
+                    "line has trailing whitespace".to_string());
+            }
+        }
+    }
+
+    fn record(&mut self, record: &Record, lines: &[Line], re_email: &Regex, re_trailer: &Regex) {
+        let header = line_of(lines, Kind::Header);
+        let trailer = line_of(lines, Kind::Trailer);
+
+        match record.option("urgency") {
+            Some(_) if URGENCIES.contains(&record.urgency().to_lowercase().as_str()) => {},
+            Some(_) => {
+                let message = format!("unknown urgency \"{}\"", record.urgency());
+                self.report(Rule::UnknownUrgency, header, message);
+            },
+            None => self.report(Rule::UnknownUrgency, header, "urgency is missing".to_string()),
+        }
+
+        if !record.version.starts_with_digit() {
+            let message = format!("upstream version \"{}\" does not start with a digit",
+                record.version.upstream());
+            self.report(Rule::UpstreamStart, header, message);
+        }
+
+        for distribution in record.distributions.iter() {
+            if !self.config.is_known_distribution(distribution) {
+                let message = format!("unknown distribution \"{}\"", distribution);
+                self.report(Rule::UnknownDistribution, header, message);
+            }
+        }
+
+        if !re_email.is_match(&record.mantainer_email) {
+            let message = format!("malformed mantainer email \"{}\"", record.mantainer_email);
+            self.report(Rule::MalformedEmail, trailer, message);
+        }
+
+        for line in lines.iter().filter(|l| l.kind == Kind::Trailer) {
+            if !re_trailer.is_match(line.content()) {
+                self.report(Rule::TrailerSpacing, line.number,
+                    "trailer must be \" -- name <email>  date\" with two spaces \
+                     before the date".to_string());
+            }
+        }
+
+        if record.mantainer_details.iter().all(|md| md.details.is_empty()) {
+            let message = format!("entry for {} has no changes", record.version);
+            self.report(Rule::EmptyEntry, header, message);
+        }
+    }
+}
+
+fn line_of(lines: &[Line], kind: Kind) -> usize {
+    lines.iter().find(|l| l.kind == kind).map_or(0, |l| l.number)
+}
+
+// Kind of a line of `Record::render`.
+fn kind_of(i: usize, content: &str) -> Kind {
+    if i == 0 {
+        Kind::Header
+    } else if content.trim().is_empty() {
+        Kind::Blank
+    } else if content.starts_with(" -- ") {
+        Kind::Trailer
+    } else if content.trim_left().starts_with("[") {
+        Kind::Mantainer
+    } else if content.trim_left().starts_with("* ") {
+        Kind::Detail
+    } else {
+        Kind::Continuation
+    }
+}
+
+// Lines `record` is written as by `Changelog::to_string`, numbered from
+// `first`: the original ones if it is untouched, its rendering and the
+// original trailing lines otherwise.
+fn written(record: &Record, first: usize) -> Vec<Line> {
+    let mut lines: Vec<Line> = match record.source {
+        Some(ref source) if !record.is_modified() => source.lines.to_vec(),
+        _ => record.render()
+            .lines()
+            .enumerate()
+            .map(|(i, l)| Line { kind: kind_of(i, l), number: 0, text: format!("{}\n", l) })
+            .collect(),
+    };
+
+    match record.source {
+        Some(ref source) if record.is_modified() => lines.extend(source.trailing().to_vec()),
+        Some(..) => {},
+        None => lines.push(Line { kind: Kind::Blank, number: 0, text: "\n".to_string() }),
+    }
+
+    for (i, line) in lines.iter_mut().enumerate() {
+        line.number = first + i;
+    }
+    lines
+}
+
+/// Checks a changelog against Debian Policy and common mistakes, as it
+/// would be written: entries added or changed since it was read are
+/// checked in their rendering. Findings are returned in file order, with
+/// the line numbers of the written file.
+pub fn lint(changelog: &Changelog, config: &LintConfig) -> Vec<Finding> {
+    let re_email = Regex::new(r"^[^@\s<>]+@[^@\s<>]+\.[^@\s<>]+$").unwrap();
+    let re_trailer = Regex::new(r"^ -- \S.*<[^>]*>  \S").unwrap();
+
+    let mut linter = Linter {
+        config: config,
+        findings: Vec::new(),
+    };
+
+    linter.lines(&changelog.leading);
+
+    let mut number = changelog.leading.len() + 1;
+    let written: Vec<Vec<Line>> = changelog.records
+        .iter()
+        .map(|record| {
+            let lines = written(record, number);
+            number += lines.len();
+            lines
+        })
+        .collect();
+
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for (i, record) in changelog.records.iter().enumerate() {
+        linter.lines(&written[i]);
+        linter.record(record, &written[i], &re_email, &re_trailer);
+
+        let header = line_of(&written[i], Kind::Header);
+
+        // entries are newest first, so each one must not be older than the next
+        if let Some(older) = changelog.records.get(i + 1) {
+            if record.date < older.date {
+                let message = format!("{} is dated before the older entry {}",
+                    record.version, older.version);
+                linter.report(Rule::DateBackwards, header, message);
+            }
+        }
+
+        let version = record.version.to_string();
+        match seen.get(&version).cloned() {
+            Some(first) => {
+                let message = format!("version {} already used on line {}", version, first);
+                linter.report(Rule::DuplicateVersion, header, message);
+            },
+            None => { seen.insert(version, header); },
+        }
+    }
+
+    let index = changelog.bugs();
+    let finder = Finder::new();
+    for (i, record) in changelog.records.iter().enumerate() {
+        let header = line_of(&written[i], Kind::Header);
+
+        for bug in record.find_bugs(&finder) {
+            let versions = &index[&bug];
+            // report on every entry but the oldest one closing the bug
+            if versions.len() > 1 && versions.last() != Some(&record.version) {
+                let message = format!("bug {} is also closed by {}",
+                    bug, versions[versions.len() - 1]);
+                linter.report(Rule::DuplicateCloses, header, message);
+            }
+        }
+    }
+
+    let mut findings = linter.findings;
+    findings.sort_by(|a, b| a.line.cmp(&b.line));
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use yaml_rust::YamlLoader;
+
+    use changelog::Changelog;
+    use record::MantainerDetails;
+    use super::{lint, LintConfig, Rule, Severity};
+
+    const CHANGELOG: &'static str = "\
+foo (1.0-1) unstable; urgency=medium
+
+  * Initial release.
+
+ -- Carol <carol@example.com>  Mon, 01 Jan 2024 10:00:00 +0000
+";
+
+    fn findings(changelog: &Changelog) -> Vec<(Rule, usize)> {
+        lint(changelog, &LintConfig::new()).iter().map(|f| (f.rule, f.line)).collect()
+    }
+
+    #[test]
+    fn parsed_changelog_is_clean() {
+        let changelog = CHANGELOG.parse::<Changelog>().unwrap();
+        assert_eq!(findings(&changelog), vec![]);
+    }
+
+    #[test]
+    fn new_entry_is_linted_with_its_written_lines() {
+        let mut changelog = CHANGELOG.parse::<Changelog>().unwrap();
+
+        let mut record = changelog.records[0].copy();
+        record.source = None;
+        record.version = "1.0-2".parse().unwrap();
+        record.mantainer_details = vec![MantainerDetails::with_details(vec![
+            "Fine.".to_string(),
+            format!("Too long: {}", "x".repeat(80)),
+            "Tab\there.".to_string(),
+            "Trailing space. ".to_string(),
+        ])];
+        changelog.records.insert(0, record);
+
+        assert_eq!(findings(&changelog), vec![
+            (Rule::LineTooLong, 4),
+            (Rule::Tab, 5),
+            (Rule::TrailingWhitespace, 6),
+        ]);
+
+        // the old entry moved down below the new one and its blank line
+        changelog.records[1].set_option("urgency", "whenever");
+        assert_eq!(findings(&changelog)[3], (Rule::UnknownUrgency, 10));
+    }
+
+    #[test]
+    fn release_codenames_are_known_distributions() {
+        for distribution in &["duke", "duke-backports", "bookworm-security"] {
+            let changelog = CHANGELOG.replace("unstable", distribution).parse::<Changelog>()
+                .unwrap();
+            assert_eq!(findings(&changelog), vec![], "{}", distribution);
+        }
+        let changelog = CHANGELOG.replace("unstable", "hamm").parse::<Changelog>().unwrap();
+        assert_eq!(findings(&changelog), vec![(Rule::UnknownDistribution, 1)]);
+    }
+
+    #[test]
+    fn upstream_not_starting_with_a_digit_is_a_warning() {
+        let changelog = CHANGELOG.replace("1.0-1", "cvs20020101-1").parse::<Changelog>().unwrap();
+        let findings = lint(&changelog, &LintConfig::new());
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].rule, findings[0].line), (Rule::UpstreamStart, 1));
+        assert_eq!(findings[0].severity, Severity::Warning);
+    }
+
+    fn config(yaml: &str) -> Result<LintConfig, String> {
+        LintConfig::from_yaml(&YamlLoader::load_from_str(yaml).unwrap()[0])
+    }
+
+    #[test]
+    fn unknown_rules_are_config_errors() {
+        match config("disable: [DCH006, no-such-rule]") {
+            Err(err) => assert!(err.contains("no-such-rule")),
+            Ok(..) => panic!("unknown rule accepted"),
+        }
+        match config("severity: {DCH999: error}") {
+            Err(err) => assert!(err.contains("DCH999")),
+            Ok(..) => panic!("unknown rule accepted"),
+        }
+        assert!(config("severity: {tab: fatal}").is_err());
+
+        let config = config("disable: [DCH006, tab]").unwrap_or(LintConfig::new());
+        assert!(config.disable.contains(&Rule::LineTooLong) && config.disable.contains(&Rule::Tab));
+    }
+}
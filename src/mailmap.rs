@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use regex::Regex;
+
+
+struct Entry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+/// Author name normalisation in the format of git's .mailmap:
+///
+/// ```text
+/// Proper Name <commit@email>
+/// <proper@email> <commit@email>
+/// Proper Name <proper@email> <commit@email>
+/// Proper Name <proper@email> Commit Name <commit@email>
+/// ```
+pub struct Mailmap {
+    entries: Vec<Entry>,
+}
+
+fn non_empty(s: Option<&str>) -> Option<String> {
+    match s.map(|s| s.trim()) {
+        Some(s) if !s.is_empty() => Some(s.to_string()),
+        _ => None,
+    }
+}
+
+impl Mailmap {
+    pub fn new() -> Mailmap {
+        Mailmap {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Mailmap {
+        let re = Regex::new(r"(?x)
+            ^\s*
+                (?P<name1>[^<\#]*?)\s*<(?P<email1>[^>]*)>   # proper (or only) identity
+            \s*
+            (?:
+                (?P<name2>[^<\#]*?)\s*<(?P<email2>[^>]*)>   # commit identity
+            )?
+        ").unwrap();
+
+        let mut it = Mailmap::new();
+
+        for line in s.lines() {
+            let cap = match re.captures(line) {
+                Some(cap) => cap,
+                None => continue, // blank lines and comments
+            };
+
+            let entry = match cap.name("email2") {
+                Some(email2) => Entry {
+                    proper_name: non_empty(cap.name("name1")),
+                    proper_email: non_empty(cap.name("email1")),
+                    commit_name: non_empty(cap.name("name2")),
+                    commit_email: email2.to_lowercase(),
+                },
+                None => Entry {
+                    proper_name: non_empty(cap.name("name1")),
+                    proper_email: None,
+                    commit_name: None,
+                    commit_email: cap.name("email1").unwrap().to_lowercase(),
+                },
+            };
+
+            it.entries.push(entry);
+        }
+
+        it
+    }
+
+    pub fn from(path: &str) -> io::Result<Mailmap> {
+        let mut s = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut s));
+        Ok(Mailmap::parse(&s))
+    }
+
+    /// Proper name and email for a commit identity. As in git, entries
+    /// naming the commit name win over entries matching the email only.
+    pub fn resolve(&self, name: &str, email: &str) -> (String, String) {
+        let email_lower = email.to_lowercase();
+
+        let entry = self.entries
+            .iter()
+            .filter(|e| e.commit_email == email_lower)
+            .filter(|e| e.commit_name.as_ref().map_or(true, |n| n == name))
+            .max_by_key(|e| e.commit_name.is_some());
+
+        match entry {
+            Some(entry) => (
+                entry.proper_name.clone().unwrap_or(name.to_string()),
+                entry.proper_email.clone().unwrap_or(email.to_string()),
+            ),
+            None => (name.to_string(), email.to_string()),
+        }
+    }
+}
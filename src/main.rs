@@ -2,10 +2,46 @@ extern crate dch;
 extern crate chrono;
 extern crate yaml_rust;
 
+use std::process;
+use std::io;
+use std::io::Read;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+
 use chrono::UTC;
+use dch::bug::{Bug, Finder};
 use dch::changelog::Changelog;
-use dch::config::Config;
+use dch::config::{Command, Config, ConfigPackage};
 use dch::dchfile::DCHFile;
+use dch::deb822;
+use dch::git;
+use dch::git::{Commit, Repository};
+use dch::hook;
+use dch::mailmap::Mailmap;
+use dch::keepachangelog;
+use dch::record::{MantainerDetails, Record};
+use dch::render;
+use dch::series;
+use dch::series::Series;
+use dch::strategy;
+use dch::strategy::VersionStrategy;
+use dch::template;
+use dch::template::Context;
+#[cfg(feature = "json")]
+use dch::json;
+use dch::lint;
+use dch::lint::{Finding, Severity};
+
+
+// Diagnostics go to stderr, so that they never end up in what the quiet
+// commands print for other programs.
+macro_rules! warn {
+    ($($arg:tt)*) => ({
+        use std::io::Write;
+        let _ = writeln!(&mut io::stderr(), $($arg)*);
+    })
+}
 
 
 fn main() {
@@ -14,45 +50,38 @@ fn main() {
         Err(e) => panic!("{}", e),
     };
 
-    println!("=> reading configuration file from: \"{}\"", config.path);
-    println!("=> working configuration is:");
-    println!("{}", config.to_string());
+    let quiet = config.command.is_quiet();
+
+    if !quiet {
+        println!("=> reading configuration file from: \"{}\"", config.path);
+        println!("=> working configuration is:");
+        println!("{}", config.to_string());
 
-    println!("start");
+        println!("start");
+    }
     let start_at = UTC::now();
 
+    let mut failed = false;
+    let mut hooks: Vec<hook::Run> = Vec::new();
+
     for package_name in &config.packages {
         match config.packages_all.get(package_name) {
             Some(package) => {
-                println!("bumping version for \"{}\"", package_name);
-                println!("using changelog at \"{}\"", package.path_changelog);
-                println!("using dchfile at \"{}\"", package.path_dchfile);
-
-                let mut dchfile = DCHFile::new(
-                    &package.path_dchfile,
-                    package_name,
-                ).unwrap();
-                println!("dchfile:");
-                println!("{}", dchfile.to_string());
-
-                let mut changelog = Changelog::new();
-                changelog.from(&package.path_changelog);
-                changelog.up(
-                    dchfile.package,
-                    dchfile.version,
-                    dchfile.distribution,
-                    dchfile.urgency,
-
-                    dchfile.details,
-
-                    config.mantainer.name.to_string(),
-                    config.mantainer.email.to_string(),
-                );
-                println!("up:");
-                println!("{}", changelog.records[0].to_string());
-                changelog.to(&package.path_changelog);
+                let ok = match config.command {
+                    Command::Up => up(&config, package_name, package, &mut hooks),
+                    Command::Lint => lint(package_name, package),
+                    Command::Parsechangelog => parsechangelog(&config, package_name, package),
+                    Command::Show => show(&config, package_name, package),
+                    Command::Export => export(&config, package_name, package),
+                    Command::Import => import(&config, package_name, package),
+                    Command::Dchfile => dchfile(&config, package_name, package),
+                };
+                failed = failed || !ok;
+            }
+            None => {
+                warn!("missing configuration for {}.", package_name);
+                failed = true;
             }
-            None => println!("missing configuration for {}.", package_name),
         }
     }
 
@@ -65,6 +94,553 @@ fn main() {
     // changelog.to("/vagrant/dch/example-project/debian-out/changelog-2");
     // changelog.to("/vagrant/dch/example-project/debian-out/changelog-3");
 
+    if !hooks.is_empty() {
+        println!("hooks:");
+        for run in hooks.iter() {
+            print!("{}", run.to_string());
+        }
+    }
+
     let finsih_at = UTC::now();
-    println!("finished at {}", finsih_at - start_at);
+    if !quiet {
+        println!("finished at {}", finsih_at - start_at);
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn read_changelog(package_name: &str, package: &ConfigPackage) -> Option<Changelog> {
+    let mut changelog = Changelog::new();
+    if let Err(diagnostics) = changelog.from(&package.path_changelog) {
+        for diagnostic in diagnostics.iter() {
+            warn!("{}", diagnostic);
+        }
+        warn!("skipping \"{}\": changelog has {} error(s)",
+            package_name, diagnostics.len());
+        return None;
+    }
+
+    Some(changelog)
+}
+
+fn read_dchfile(config: &Config, package_name: &str, package: &ConfigPackage) -> Option<DCHFile> {
+    let profile = config.profile.as_ref().map(|p| &p[..]);
+    let branch = || Repository::new(&package.path).branch();
+    match DCHFile::new(&package.path_dchfile, package_name, profile, &branch) {
+        Ok(dchfile) => Some(dchfile),
+        Err(errors) => {
+            for err in errors.iter() {
+                let path = err.path().unwrap_or(&package.path_dchfile);
+                match err.position() {
+                    Some((line, column)) => warn!("{}:{}:{}: {}", path, line, column, err),
+                    None => warn!("{}: {}", path, err),
+                }
+            }
+            warn!("skipping \"{}\": dchfile has {} error(s)", package_name, errors.len());
+            None
+        }
+    }
+}
+
+// Prints findings and tells whether any of them is an error.
+fn report(package: &ConfigPackage, findings: &[Finding]) -> bool {
+    for finding in findings.iter() {
+        println!("{}:{}", package.path_changelog, finding);
+    }
+
+    findings.iter().any(|f| f.severity == Severity::Error)
+}
+
+// Commits of the package repository since `--since` or else the release of
+// the newest changelog entry, noise left out. Without either there is no
+// telling where the new entry starts, and the whole history is not it.
+fn commits(config: &Config, package: &ConfigPackage, changelog: &Changelog, ignore: &[String])
+    -> Result<Vec<Commit>, String>
+{
+    let repository = Repository::new(&package.path);
+
+    let since = match (config.since.as_ref(), changelog.records.first()) {
+        (Some(since), _) => since.to_string(),
+        (None, Some(record)) => match repository.release(&record.version, &package.path_changelog) {
+            Some(since) => since,
+            None => return Err(format!("no release found for {}, give the commit it was \
+                                        released from with --since", record.version)),
+        },
+        (None, None) => return Err("changelog has no entries, give the commit to start \
+                                    from with --since".to_string()),
+    };
+
+    let commits = match repository.log(Some(&since)) {
+        Ok(commits) => commits,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    git::filter(commits, ignore).map_err(|err| format!("invalid git-ignore pattern {}", err))
+}
+
+// Variables for Dchfile templates; "version" is added once it is known.
+fn context(config: &Config, package: &ConfigPackage, dchfile: &DCHFile, changelog: &Changelog,
+    log: &[Commit], distribution: &str) -> Context
+{
+    let mut it = Context::new();
+    let previous = changelog.records.first().map(|r| &r.version);
+    let repository = Repository::new(&package.path);
+
+    it.set("package", &dchfile.package);
+    it.set_option("previous", previous.map(|v| v.to_string()).as_ref().map(|v| &v[..]));
+    it.set_option("upstream", previous.map(|v| v.upstream()));
+    it.set("distribution", distribution);
+    it.set("urgency", &dchfile.urgency);
+    it.set("mantainer", &format!("{} <{}>", config.mantainer.name, config.mantainer.email));
+    it.set("mantainer.name", &config.mantainer.name);
+    it.set("mantainer.email", &config.mantainer.email);
+    it.set_option("git.sha", repository.head().as_ref().map(|s| &s[..]));
+    it.set_option("git.branch", repository.branch().as_ref().map(|s| &s[..]));
+
+    // bugs closed by the commits since the previous release
+    let mut closes: Vec<String> = Vec::new();
+    let finder = Finder::new();
+    for commit in log.iter() {
+        for bug in finder.parse(&format!("{}\n{}", commit.subject, commit.body)) {
+            let number = bug.number().to_string();
+            if let Bug::Debian(..) = bug {
+                if !closes.contains(&number) {
+                    closes.push(number);
+                }
+            }
+        }
+    }
+    it.set_list("closes", closes);
+
+    it
+}
+
+fn up(config: &Config, package_name: &str, package: &ConfigPackage, hooks: &mut Vec<hook::Run>)
+    -> bool
+{
+    println!("bumping version for \"{}\"", package_name);
+    println!("using changelog at \"{}\"", package.path_changelog);
+    println!("using dchfile at \"{}\"", package.path_dchfile);
+
+    let mut dchfile = match read_dchfile(config, package_name, package) {
+        Some(dchfile) => dchfile,
+        None => return false,
+    };
+    println!("dchfile:");
+    println!("{}", dchfile.to_string());
+
+    let mut changelog = match read_changelog(package_name, package) {
+        Some(changelog) => changelog,
+        None => return false,
+    };
+
+    let mut mantainer_details: Vec<MantainerDetails> = Vec::new();
+
+    if let Some(ref changelog_md) = dchfile.changelog_md {
+        let path = Path::new(&package.path).join(changelog_md);
+        let releases = match keepachangelog::from(&path.to_string_lossy()) {
+            Ok(releases) => releases,
+            Err(err) => {
+                println!("skipping \"{}\": failed to read \"{}\": {}",
+                    package_name, path.display(), err);
+                return false;
+            }
+        };
+
+        let release = dchfile.changelog_md_release.as_ref().map(|r| &r[..]);
+        match keepachangelog::release(&releases, release) {
+            Some(release) => mantainer_details.extend(release.mantainer_details()),
+            None => {
+                println!("skipping \"{}\": no release {} in \"{}\"",
+                    package_name, release.unwrap_or("at all"), path.display());
+                return false;
+            }
+        }
+    }
+
+    let needs_commits = strategy::needs_commits(&dchfile.version_strategy);
+    let log = if dchfile.git_log || needs_commits {
+        match commits(config, package, &changelog, &dchfile.git_ignore) {
+            Ok(log) => log,
+            Err(err) => {
+                println!("skipping \"{}\": {}", package_name, err);
+                return false;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let distribution = match config.derivation {
+        Some(ref derivation) => derivation.distribution(),
+        None => dchfile.distribution.to_string(),
+    };
+
+    let mut context = context(config, package, &dchfile, &changelog, &log, &distribution);
+    let version_template = match template::render(&dchfile.version, &context) {
+        Ok(version_template) => version_template,
+        Err(err) => {
+            println!("skipping \"{}\": version: {}", package_name, err);
+            return false;
+        }
+    };
+
+    // a backport or an upload kind with its own version scheme wins over
+    // the package's version strategy
+    let strategy: Result<Box<VersionStrategy>, _> = match config.derivation {
+        Some(ref derivation) => Ok(Box::new(derivation.strategy(&changelog.records))),
+        None => match config.upload.strategy() {
+            Some(strategy) => Ok(strategy),
+            None => strategy::new(
+                &dchfile.version_strategy,
+                &version_template,
+                if needs_commits { log.to_vec() } else { Vec::new() },
+            ),
+        },
+    };
+    let strategy = match strategy {
+        Ok(strategy) => strategy,
+        Err(err) => {
+            println!("skipping \"{}\": {}", package_name, err);
+            return false;
+        }
+    };
+
+    let version = match changelog.next_version(&*strategy, dchfile.disambiguate) {
+        Ok(version) => version,
+        Err(err) => {
+            println!("skipping \"{}\": {}", package_name, err);
+            return false;
+        }
+    };
+    context.set("version", &version.to_string());
+
+    let mut details: Vec<String> = Vec::new();
+    for detail in dchfile.details.iter() {
+        match template::render(detail, &context) {
+            Ok(detail) => details.push(detail),
+            Err(err) => {
+                println!("skipping \"{}\": detail \"{}\": {}", package_name, detail, err);
+                return false;
+            }
+        }
+    }
+    mantainer_details.insert(0, MantainerDetails::with_details(details));
+
+    if dchfile.git_log {
+        let mailmap = match config.mailmap {
+            Some(ref path) => match Mailmap::from(path) {
+                Ok(mailmap) => mailmap,
+                Err(err) => {
+                    println!("skipping \"{}\": failed to read mailmap \"{}\": {}",
+                        package_name, path, err);
+                    return false;
+                }
+            },
+            None => Mailmap::new(),
+        };
+
+        mantainer_details.extend(git::authors(log, &mailmap,
+            &config.mantainer.name, &config.mantainer.email));
+    }
+
+    if let Err(err) = changelog.up(
+        &*strategy,
+
+        dchfile.package,
+        version,
+        distribution,
+        dchfile.urgency,
+
+        mantainer_details,
+
+        config.mantainer.name.to_string(),
+        config.mantainer.email.to_string(),
+    ) {
+        println!("skipping \"{}\": {}", package_name, err);
+        return false;
+    }
+    config.upload.apply(&mut changelog.records[0]);
+    if let Some(ref derivation) = config.derivation {
+        derivation.apply(&mut changelog.records[0]);
+    }
+    println!("up:");
+    println!("{}", changelog.records[0].to_string());
+
+    if package.lint.enforce {
+        let findings = lint::lint(&changelog, &package.lint);
+        if report(package, &findings) {
+            println!("skipping \"{}\": lint found errors", package_name);
+            return false;
+        }
+    }
+
+    let env = hook::Env {
+        package: changelog.records[0].package.to_string(),
+        old_version: changelog.records.get(1).map(|r| r.version.to_string()),
+        new_version: changelog.records[0].version.to_string(),
+        // hooks run in the package directory
+        changelog: fs::canonicalize(&package.path_changelog)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or(package.path_changelog.to_string()),
+        distribution: changelog.records[0].distributions.join(" "),
+    };
+
+    for argv in dchfile.before.iter() {
+        let run = hook::run(package_name, "before", argv, &env, &package.path);
+        let success = run.success();
+        hooks.push(run);
+        if !success {
+            warn!("skipping \"{}\": before hook \"{}\" failed", package_name, argv.join(" "));
+            return false;
+        }
+    }
+
+    // every variant is made before anything is written, so that a failing
+    // one leaves all changelogs as they were
+    let mut variants = match fan_out(package, &dchfile.series, &dchfile.series_output, &changelog) {
+        Some(variants) => variants,
+        None => {
+            println!("skipping \"{}\": series changelogs could not be made", package_name);
+            return false;
+        }
+    };
+
+    if let Err(err) = changelog.to(&package.path_changelog) {
+        println!("failed to write changelog \"{}\": {}",
+            package.path_changelog, err);
+        return false;
+    }
+
+    let mut ok = true;
+    for &mut (ref name, ref path, ref mut variant) in variants.iter_mut() {
+        if let Err(err) = series::write(variant, path) {
+            println!("failed to write changelog \"{}\": {}", path, err);
+            ok = false;
+            break;
+        }
+        println!("series \"{}\": {} at \"{}\"", name, variant.records[0].version, path);
+    }
+
+    for argv in dchfile.after.iter() {
+        let run = hook::run(package_name, "after", argv, &env, &package.path);
+        if !run.success() {
+            warn!("after hook \"{}\" of \"{}\" failed", argv.join(" "), package_name);
+            ok = false;
+        }
+        hooks.push(run);
+    }
+
+    ok
+}
+
+// One changelog variant per series with the path it goes to, next to the
+// package changelog.
+fn fan_out(package: &ConfigPackage, targets: &[Series], output: &str, changelog: &Changelog)
+    -> Option<Vec<(String, String, Changelog)>>
+{
+    let mut variants: Vec<(String, String, Changelog)> = Vec::new();
+
+    for target in targets.iter() {
+        let path = Path::new(&package.path).join(target.path(output));
+        let path = path.to_string_lossy().into_owned();
+
+        // the variant written by the previous run, if any, tells how many
+        // uploads of this version the series had; one that cannot be read
+        // must not be overwritten by a version it already handed out
+        let existing = if Path::new(&path).exists() {
+            let mut existing = Changelog::new();
+            if let Err(diagnostics) = existing.from(&path) {
+                for diagnostic in diagnostics.iter() {
+                    warn!("{}", diagnostic);
+                }
+                warn!("series \"{}\": changelog \"{}\" has {} error(s)",
+                    target.name, path, diagnostics.len());
+                return None;
+            }
+            Some(existing)
+        } else {
+            None
+        };
+
+        match target.variant(changelog, existing.as_ref()) {
+            Ok(variant) => variants.push((target.name.to_string(), path, variant)),
+            Err(err) => {
+                println!("failed to make changelog for series \"{}\": {}", target.name, err);
+                return None;
+            }
+        }
+    }
+
+    Some(variants)
+}
+
+fn lint(package_name: &str, package: &ConfigPackage) -> bool {
+    println!("linting \"{}\"", package_name);
+
+    let changelog = match read_changelog(package_name, package) {
+        Some(changelog) => changelog,
+        None => return false,
+    };
+
+    let findings = lint::lint(&changelog, &package.lint);
+    !report(package, &findings)
+}
+
+fn parsechangelog(config: &Config, package_name: &str, package: &ConfigPackage) -> bool {
+    let changelog = match read_changelog(package_name, package) {
+        Some(changelog) => changelog,
+        None => return false,
+    };
+
+    let records = changelog.select(&config.selection);
+    let (paragraph, warnings) = deb822::parsechangelog(&records);
+    for warning in warnings.iter() {
+        warn!("{}: {}", package.path_changelog, warning);
+    }
+
+    match config.field {
+        Some(ref field) => match paragraph.get(field) {
+            Some(value) => println!("{}", value),
+            // a known field the entries do not have, like "Closes"
+            None if deb822::is_field(field) => println!(""),
+            None => {
+                warn!("unknown field \"{}\"", field);
+                return false;
+            },
+        },
+        None => print!("{}", paragraph.to_string()),
+    }
+
+    true
+}
+
+fn show(config: &Config, package_name: &str, package: &ConfigPackage) -> bool {
+    let changelog = match read_changelog(package_name, package) {
+        Some(changelog) => changelog,
+        None => return false,
+    };
+
+    for record in changelog.select(&config.selection) {
+        print!("{}", record.render());
+        println!("");
+    }
+
+    true
+}
+
+fn export(config: &Config, package_name: &str, package: &ConfigPackage) -> bool {
+    let changelog = match read_changelog(package_name, package) {
+        Some(changelog) => changelog,
+        None => return false,
+    };
+    let records = changelog.select(&config.selection);
+
+    match &config.format[..] {
+        #[cfg(feature = "json")]
+        "json" => println!("{}", json::export(&changelog, &records)),
+        "markdown" => print!("{}", render::markdown_document(&records, &package.render)),
+        "html" => print!("{}", render::html_document(&records, &package.render)),
+        format => {
+            warn!("dch was built without support for exporting {}", format);
+            return false;
+        }
+    }
+
+    true
+}
+
+fn dchfile(config: &Config, package_name: &str, package: &ConfigPackage) -> bool {
+    let mut dchfile = match read_dchfile(config, package_name, package) {
+        Some(dchfile) => dchfile,
+        None => return false,
+    };
+
+    print!("{}", dchfile.to_string());
+
+    true
+}
+
+fn read_input(path: &str) -> io::Result<String> {
+    let mut s = String::new();
+    if path == "-" {
+        try!(io::stdin().read_to_string(&mut s));
+    } else {
+        try!(try!(File::open(path)).read_to_string(&mut s));
+    }
+    Ok(s)
+}
+
+// Builds a whole changelog from the release history of a Keep a Changelog
+// file; package, distribution and urgency come from the Dchfile.
+fn seed(config: &Config, package_name: &str, package: &ConfigPackage, input: &str) -> Option<Changelog> {
+    let dchfile = match read_dchfile(config, package_name, package) {
+        Some(dchfile) => dchfile,
+        None => return None,
+    };
+
+    let mut template = Record::new();
+    template.package = dchfile.package;
+    template.distributions = dchfile.distribution
+        .split_whitespace()
+        .map(|d| d.to_string())
+        .collect();
+    template.set_option("urgency", &dchfile.urgency);
+    template.mantainer_name = config.mantainer.name.to_string();
+    template.mantainer_email = config.mantainer.email.to_string();
+
+    let releases = keepachangelog::parse(input);
+    match keepachangelog::records(&releases, &template) {
+        Ok(records) => {
+            let mut changelog = Changelog::new();
+            changelog.records = records;
+            Some(changelog)
+        },
+        Err(err) => {
+            println!("failed to import \"{}\": {}", package_name, err);
+            None
+        }
+    }
+}
+
+fn import(config: &Config, package_name: &str, package: &ConfigPackage) -> bool {
+    let path = config.input.as_ref().map_or("-", |i| &i[..]);
+    println!("importing \"{}\" from \"{}\"", package_name, path);
+
+    let input = match read_input(path) {
+        Ok(input) => input,
+        Err(err) => {
+            println!("failed to read \"{}\": {}", path, err);
+            return false;
+        }
+    };
+
+    let mut changelog: Changelog = match &config.format[..] {
+        #[cfg(feature = "json")]
+        "json" => match json::import(&input) {
+            Ok(changelog) => changelog,
+            Err(err) => {
+                println!("failed to import \"{}\": {}", path, err);
+                return false;
+            }
+        },
+        "keepachangelog" => match seed(config, package_name, package, &input) {
+            Some(changelog) => changelog,
+            None => return false,
+        },
+        format => {
+            warn!("dch was built without support for importing {}", format);
+            return false;
+        }
+    };
+
+    if let Err(err) = changelog.to(&package.path_changelog) {
+        println!("failed to write changelog \"{}\": {}",
+            package.path_changelog, err);
+        return false;
+    }
+
+    true
 }
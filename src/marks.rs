@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use yaml_rust::Event;
+use yaml_rust::parser::{MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, ScanError};
+
+
+/// Positions of the nodes of a YAML document, which `Yaml` does not keep.
+/// Nodes are addressed by dotted paths of keys and list indexes:
+/// "details", "details.1", "before.0.2".
+pub struct Marks {
+    values: HashMap<String, Marker>,
+    keys: HashMap<String, Marker>,
+}
+
+enum Frame {
+    Map(String, Option<String>),
+    Seq(String, usize),
+}
+
+struct Receiver {
+    stack: Vec<Frame>,
+    marks: Marks,
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+impl Receiver {
+    // Path of the value node that starts now.
+    fn next_path(&mut self) -> String {
+        match self.stack.last_mut() {
+            Some(&mut Frame::Map(ref path, Some(ref key))) => join(path, key),
+            Some(&mut Frame::Seq(ref path, ref mut index)) => {
+                *index += 1;
+                join(path, &(*index - 1).to_string())
+            },
+            _ => String::new(),
+        }
+    }
+
+    // A value of the current mapping is complete, a key comes next.
+    fn done(&mut self) {
+        if let Some(&mut Frame::Map(_, ref mut key)) = self.stack.last_mut() {
+            *key = None;
+        }
+    }
+
+    fn is_key(&self) -> bool {
+        match self.stack.last() {
+            Some(&Frame::Map(_, None)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl MarkedEventReceiver for Receiver {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                if self.is_key() {
+                    if let Some(&mut Frame::Map(ref path, ref mut key)) = self.stack.last_mut() {
+                        // the start of a block mapping is only marked after
+                        // its first key
+                        if let Some(start) = self.marks.values.get_mut(path) {
+                            if mark.index() < start.index() {
+                                *start = mark;
+                            }
+                        }
+                        self.marks.keys.insert(join(path, &value), mark);
+                        *key = Some(value);
+                    }
+                } else {
+                    let path = self.next_path();
+                    self.marks.values.insert(path, mark);
+                    self.done();
+                }
+            },
+            Event::Alias(..) => {
+                let path = self.next_path();
+                self.marks.values.insert(path, mark);
+                self.done();
+            },
+            Event::SequenceStart(..) => {
+                let path = self.next_path();
+                self.marks.values.insert(path.clone(), mark);
+                self.stack.push(Frame::Seq(path, 0));
+            },
+            Event::MappingStart(..) => {
+                let path = self.next_path();
+                self.marks.values.insert(path.clone(), mark);
+                self.stack.push(Frame::Map(path, None));
+            },
+            Event::SequenceEnd | Event::MappingEnd => {
+                self.stack.pop();
+                self.done();
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Marks {
+    /// Marks of the first document of `s`.
+    pub fn load(s: &str) -> Result<Marks, ScanError> {
+        let mut receiver = Receiver {
+            stack: Vec::new(),
+            marks: Marks {
+                values: HashMap::new(),
+                keys: HashMap::new(),
+            },
+        };
+
+        try!(Parser::new(s.chars()).load(&mut receiver, false));
+
+        Ok(receiver.marks)
+    }
+
+    /// 1-based line and column of the value at `path`, if there is one.
+    pub fn value(&self, path: &str) -> Option<(usize, usize)> {
+        self.values.get(path).map(position)
+    }
+
+    /// 1-based line and column of the key of the value at `path`, if there
+    /// is one.
+    pub fn key(&self, path: &str) -> Option<(usize, usize)> {
+        self.keys.get(path).map(position)
+    }
+}
+
+fn position(mark: &Marker) -> (usize, usize) {
+    (mark.line(), mark.col() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Marks;
+
+    #[test]
+    fn paths_of_keys_and_values() {
+        let marks = Marks::load("# settings\npackage: dch\nbefore:\n  - [make, check]\n\
+                                 profiles:\n  staging: {urgency: low}\n").unwrap();
+
+        assert_eq!(marks.value(""), Some((2, 1)));
+        assert_eq!(marks.key("package"), Some((2, 1)));
+        assert_eq!(marks.value("package"), Some((2, 10)));
+        assert_eq!(marks.value("before.0"), Some((4, 5)));
+        assert_eq!(marks.value("before.0.1"), Some((4, 12)));
+        assert_eq!(marks.value("profiles.staging"), Some((6, 12)));
+        assert_eq!(marks.key("profiles.staging.urgency"), Some((6, 13)));
+    }
+
+    #[test]
+    fn unknown_path_has_no_position() {
+        assert_eq!(Marks::load("a: b\n").unwrap().value("c.0"), None);
+        assert_eq!(Marks::load("a: b\n").unwrap().key("c"), None);
+    }
+}
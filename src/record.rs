@@ -1,6 +1,19 @@
 use chrono::{DateTime,UTC,FixedOffset,Local};
+use bug::{Bug, Finder};
+use cst::Source;
+use render;
+use render::RenderConfig;
+use version::DebianVersion;
+
+
+/// Urgency values known to Debian Policy, from lowest to highest as dpkg
+/// ranks them.
+pub const URGENCIES: &'static [&'static str] = &[
+    "low", "medium", "high", "critical", "emergency",
+];
 
 #[derive(Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct MantainerDetails {
     pub mantainer: String,
     pub details: Vec<String>,
@@ -13,48 +26,208 @@ impl MantainerDetails {
             details: Vec::new(),
         }
     }
+
+    pub fn with_details(details: Vec<String>) -> MantainerDetails {
+        let mut it = MantainerDetails::new();
+        it.details = details;
+        it
+    }
+
+    /// Joins blocks of the same mantainer, keeping the order in which
+    /// mantainers first appear, and drops blocks without details. The
+    /// result always has at least one block.
+    pub fn merge(blocks: Vec<MantainerDetails>) -> Vec<MantainerDetails> {
+        let mut merged: Vec<MantainerDetails> = Vec::new();
+
+        for block in blocks.into_iter().filter(|b| !b.details.is_empty()) {
+            match merged.iter().position(|m| m.mantainer == block.mantainer) {
+                Some(i) => merged[i].details.extend(block.details),
+                None => merged.push(block),
+            }
+        }
+
+        if merged.is_empty() {
+            merged.push(MantainerDetails::new());
+        }
+
+        merged
+    }
 }
 
 pub struct Record {
     pub package: String,
-    pub version: String,
-    pub distribution: String,
-    pub urgency: String,
+    pub version: DebianVersion,
+    pub distributions: Vec<String>,
+    /// keyword options after the ";" in the order they were written,
+    /// e.g. `[("urgency", "high (security)"), ("binary-only", "yes")]`
+    pub options: Vec<(String, String)>,
 
     pub mantainer_details: Vec<MantainerDetails>,
 
     pub mantainer_name: String,
     pub mantainer_email: String,
     pub date: DateTime<FixedOffset>,
+
+    /// original text when the record was parsed from a file
+    pub source: Option<Source>,
 }
 
 impl Record {
     pub fn new() -> Record {
         Record {
             package: "".to_string(),
-            version: "".to_string(),
-            distribution: "".to_string(),
-            urgency: "".to_string(),
+            version: DebianVersion::default(),
+            distributions: Vec::new(),
+            options: Vec::new(),
 
             mantainer_details: vec![MantainerDetails::new()],
 
             mantainer_name: "".to_string(),
             mantainer_email: "".to_string(),
             date: UTC::now().with_timezone(Local::now().offset()),
+
+            source: None,
         }
     }
 
     pub fn _log(&self) { println!("{}", self.to_string()); }
 
-    pub fn to_string(&self) -> String {
-        let mut s = String::new();
+    pub fn distribution(&self) -> String {
+        self.distributions.join(" ")
+    }
+
+    /// Keyword lookup is case-insensitive, as required by Debian Policy.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|&&(ref k, _)| k.to_lowercase() == key.to_lowercase())
+            .map(|&(_, ref v)| &v[..])
+    }
+
+    pub fn set_option(&mut self, key: &str, value: &str) {
+        let lower = key.to_lowercase();
+        for &mut (ref k, ref mut v) in self.options.iter_mut() {
+            if k.to_lowercase() == lower {
+                *v = value.to_string();
+                return;
+            }
+        }
+        self.options.push((key.to_string(), value.to_string()));
+    }
+
+    /// Urgency value without its comment: "high" for "urgency=high (security)".
+    pub fn urgency(&self) -> &str {
+        match self.option("urgency") {
+            Some(urgency) => urgency.split(' ').next().unwrap_or(""),
+            None => "",
+        }
+    }
+
+    /// Urgency comment without parentheses: "security" for "urgency=high (security)".
+    pub fn urgency_comment(&self) -> Option<&str> {
+        match self.option("urgency") {
+            Some(urgency) => match (urgency.find('('), urgency.rfind(')')) {
+                (Some(start), Some(end)) if start < end => Some(&urgency[start + 1..end]),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    /// Bugs referenced by the details of all mantainer blocks, each once,
+    /// in the order they first appear.
+    pub fn bugs(&self) -> Vec<Bug> {
+        self.find_bugs(&Finder::new())
+    }
 
-        s.push_str(&format!("{package} ({version}) {distribution}; urgency={urgency}\n",
+    /// `bugs` with patterns compiled once for many records.
+    pub fn find_bugs(&self, finder: &Finder) -> Vec<Bug> {
+        let mut bugs: Vec<Bug> = Vec::new();
+
+        for md in self.mantainer_details.iter() {
+            for detail in md.details.iter() {
+                for bug in finder.parse(detail) {
+                    if !bugs.contains(&bug) {
+                        bugs.push(bug);
+                    }
+                }
+            }
+        }
+
+        bugs
+    }
+
+    /// Debian bugs closed by this entry ("Closes: #123, #456").
+    pub fn closes(&self) -> Vec<u32> {
+        self.bugs()
+            .into_iter()
+            .filter_map(|bug| match bug { Bug::Debian(n) => Some(n), _ => None })
+            .collect()
+    }
+
+    /// Launchpad bugs closed by this entry ("LP: #789").
+    pub fn launchpad_bugs(&self) -> Vec<u32> {
+        self.bugs()
+            .into_iter()
+            .filter_map(|bug| match bug { Bug::Launchpad(n) => Some(n), _ => None })
+            .collect()
+    }
+
+    pub fn header(&self) -> String {
+        let options: Vec<String> = self.options
+            .iter()
+            .map(|&(ref k, ref v)| format!("{}={}", k, v))
+            .collect();
+
+        format!("{package} ({version}) {distribution}; {options}",
             package=self.package,
             version=self.version,
-            distribution=self.distribution,
-            urgency=self.urgency,
-        ));
+            distribution=self.distribution(),
+            options=options.join(", "),
+        )
+    }
+
+    /// Whether the record no longer matches the text it was parsed from.
+    /// Records built in memory are always modified.
+    pub fn is_modified(&self) -> bool {
+        match self.source {
+            Some(ref source) => source.rendered != self.render(),
+            None => true,
+        }
+    }
+
+    /// Original text if the record is untouched, canonical rendering
+    /// followed by the original trailing lines otherwise.
+    pub fn to_string(&self) -> String {
+        match self.source {
+            Some(ref source) if !self.is_modified() => source.to_string(),
+            Some(ref source) => {
+                let mut s = self.render();
+                if source.terminator() != "\n" {
+                    s = s.replace("\n", source.terminator());
+                }
+                for line in source.trailing().iter() {
+                    s.push_str(&line.text);
+                }
+                s
+            },
+            None => self.render(),
+        }
+    }
+
+    pub fn to_markdown(&self, config: &RenderConfig) -> String {
+        render::markdown(self, config)
+    }
+
+    pub fn to_html(&self, config: &RenderConfig) -> String {
+        render::html(self, config)
+    }
+
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+
+        s.push_str(&self.header());
+        s.push_str("\n");
 
         s.push_str("\n");
         for md in self.mantainer_details.iter() {
@@ -81,15 +254,17 @@ impl Record {
     pub fn copy(&self) -> Record {
         Record {
             package: String::from(self.package.to_string()),
-            version: String::from(self.version.to_string()),
-            distribution: String::from(self.distribution.to_string()),
-            urgency: String::from(self.urgency.to_string()),
+            version: self.version.clone(),
+            distributions: self.distributions.to_vec(),
+            options: self.options.to_vec(),
 
             mantainer_details: self.mantainer_details.to_vec(),
 
             mantainer_name: String::from(self.mantainer_name.to_string()),
             mantainer_email: String::from(self.mantainer_email.to_string()),
             date: self.date.clone(),
+
+            source: self.source.clone(),
         }
     }
 }
@@ -0,0 +1,294 @@
+use std::cmp;
+
+use yaml_rust::Yaml;
+
+use bug::{Bug, Finder};
+use record::{Record, MantainerDetails};
+
+
+const DEBIAN_BUG_URL: &'static str = "https://bugs.debian.org/{}";
+const LAUNCHPAD_BUG_URL: &'static str = "https://bugs.launchpad.net/bugs/{}";
+
+const STYLE: &'static str = "\
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; }
+.badge { display: inline-block; padding: 0 .5em; border-radius: .3em; \
+         background: #eee; font-size: .8em; }
+.urgency-high, .urgency-emergency, .urgency-critical { background: #fcc; }
+.trailer { color: #666; font-size: .9em; }";
+
+
+/// Markdown and HTML rendering settings, the "render" key of a package in
+/// .dchrc:
+///
+/// ```yaml
+/// render:
+///   bug-url: https://bugs.example.com/show_bug.cgi?id={}
+///   launchpad-url: https://bugs.launchpad.net/bugs/{}
+/// ```
+///
+/// "{}" is replaced with the bug number.
+pub struct RenderConfig {
+    pub bug_url: String,
+    pub launchpad_url: String,
+}
+
+impl RenderConfig {
+    pub fn new() -> RenderConfig {
+        RenderConfig {
+            bug_url: DEBIAN_BUG_URL.to_string(),
+            launchpad_url: LAUNCHPAD_BUG_URL.to_string(),
+        }
+    }
+
+    pub fn from_yaml(yaml: &Yaml) -> RenderConfig {
+        RenderConfig {
+            bug_url: yaml["bug-url"].as_str().unwrap_or(DEBIAN_BUG_URL).to_string(),
+            launchpad_url: yaml["launchpad-url"].as_str().unwrap_or(LAUNCHPAD_BUG_URL).to_string(),
+        }
+    }
+
+    pub fn url(&self, bug: Bug) -> String {
+        let template = match bug {
+            Bug::Debian(..) => &self.bug_url,
+            Bug::Launchpad(..) => &self.launchpad_url,
+        };
+        template.replace("{}", &bug.number().to_string())
+    }
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut it = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' => {
+                it.push('\\');
+                it.push(c);
+            },
+            _ => it.push(c),
+        }
+    }
+    it
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Escapes `text` and turns its bug references into links.
+fn linkify<E, L>(text: &str, finder: &Finder, escape: E, link: L) -> String
+    where E: Fn(&str) -> String, L: Fn(&str, Bug) -> String
+{
+    let mut it = String::new();
+    let mut at = 0;
+
+    for (start, end, bug) in finder.find(text) {
+        it.push_str(&escape(&text[at..start]));
+        it.push_str(&link(&text[start..end], bug));
+        at = end;
+    }
+    it.push_str(&escape(&text[at..]));
+
+    it
+}
+
+// Leading ASCII spaces of `line`; other whitespace is content.
+fn spaces(line: &str) -> usize {
+    line.len() - line.trim_left_matches(' ').len()
+}
+
+// A detail is its first line plus continuation lines as written in the
+// changelog; continuation lines lose the indentation they share.
+fn detail_lines(detail: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = detail.lines().collect();
+
+    let indent = lines
+        .iter()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| spaces(l))
+        .min()
+        .unwrap_or(0);
+
+    for line in lines.iter_mut().skip(1) {
+        let cut = cmp::min(spaces(line), indent);
+        *line = &line[cut..];
+    }
+
+    lines
+}
+
+fn trailer(record: &Record) -> (String, String) {
+    (format!("{} <{}>", record.mantainer_name, record.mantainer_email),
+     record.date.format("%a, %d %b %Y %H:%M:%S %z").to_string())
+}
+
+pub fn markdown(record: &Record, config: &RenderConfig) -> String {
+    markdown_section(record, config, &Finder::new())
+}
+
+fn markdown_section(record: &Record, config: &RenderConfig, finder: &Finder) -> String {
+    let link = |text: &str, bug: Bug| format!("[{}]({})", escape_markdown(text), config.url(bug));
+    let mut s = String::new();
+
+    s.push_str(&format!("## {}\n\n", escape_markdown(&record.version.to_string())));
+
+    let mut badges: Vec<String> = record.distributions
+        .iter()
+        .map(|d| format!("`{}`", d))
+        .collect();
+    for &(ref key, ref value) in record.options.iter() {
+        badges.push(format!("`{}: {}`", key, value));
+    }
+    s.push_str(&badges.join(" "));
+    s.push_str("\n\n");
+
+    for md in record.mantainer_details.iter() {
+        if !md.mantainer.is_empty() {
+            s.push_str(&format!("### {}\n\n", escape_markdown(&md.mantainer)));
+        }
+
+        for detail in md.details.iter() {
+            let mut prefix = "- ";
+            for line in detail_lines(detail) {
+                s.push_str(prefix);
+                s.push_str(&linkify(line, finder, escape_markdown, &link));
+                s.push_str("\n");
+                prefix = "  ";
+            }
+        }
+
+        if !md.details.is_empty() {
+            s.push_str("\n");
+        }
+    }
+
+    let (mantainer, date) = trailer(record);
+    s.push_str(&format!("*{} — {}*\n", escape_markdown(&mantainer), date));
+
+    s
+}
+
+fn html_details(md: &MantainerDetails, config: &RenderConfig, finder: &Finder) -> String {
+    let link = |text: &str, bug: Bug| format!("<a href=\"{}\">{}</a>",
+        escape_html(&config.url(bug)), escape_html(text));
+    let mut s = String::new();
+
+    if !md.mantainer.is_empty() {
+        s.push_str(&format!("<h3>{}</h3>\n", escape_html(&md.mantainer)));
+    }
+
+    if md.details.is_empty() {
+        return s;
+    }
+
+    s.push_str("<ul>\n");
+    for detail in md.details.iter() {
+        let lines: Vec<String> = detail_lines(detail)
+            .into_iter()
+            .map(|line| linkify(line, finder, escape_html, &link))
+            .collect();
+        s.push_str(&format!("<li>{}</li>\n", lines.join("<br>\n")));
+    }
+    s.push_str("</ul>\n");
+
+    s
+}
+
+/// A `<section>` for the record, without the surrounding document.
+pub fn html(record: &Record, config: &RenderConfig) -> String {
+    html_section(record, config, &Finder::new())
+}
+
+fn html_section(record: &Record, config: &RenderConfig, finder: &Finder) -> String {
+    let mut s = String::new();
+    let version = escape_html(&record.version.to_string());
+
+    s.push_str(&format!("<section id=\"v{}\">\n", version));
+    s.push_str(&format!("<h2>{}</h2>\n", version));
+
+    s.push_str("<p>");
+    for distribution in record.distributions.iter() {
+        s.push_str(&format!("<span class=\"badge distribution\">{}</span> ",
+            escape_html(distribution)));
+    }
+    for &(ref key, ref value) in record.options.iter() {
+        let class = if key.to_lowercase() == "urgency" {
+            format!("badge urgency urgency-{}", escape_html(&record.urgency().to_lowercase()))
+        } else {
+            "badge".to_string()
+        };
+        s.push_str(&format!("<span class=\"{}\">{}: {}</span> ",
+            class, escape_html(key), escape_html(value)));
+    }
+    s.push_str("</p>\n");
+
+    for md in record.mantainer_details.iter() {
+        s.push_str(&html_details(md, config, finder));
+    }
+
+    let (mantainer, date) = trailer(record);
+    s.push_str(&format!("<p class=\"trailer\">{} — {}</p>\n",
+        escape_html(&mantainer), escape_html(&date)));
+    s.push_str("</section>\n");
+
+    s
+}
+
+/// Release notes for `records` (newest first) as a Markdown document.
+pub fn markdown_document(records: &[&Record], config: &RenderConfig) -> String {
+    let mut s = String::new();
+
+    if let Some(first) = records.first() {
+        s.push_str(&format!("# {}\n\n", escape_markdown(&first.package)));
+    }
+
+    let finder = Finder::new();
+    let sections: Vec<String> = records
+        .iter()
+        .map(|r| markdown_section(r, config, &finder))
+        .collect();
+    s.push_str(&sections.join("\n"));
+
+    s
+}
+
+/// Release notes for `records` (newest first) as a standalone HTML page.
+pub fn html_document(records: &[&Record], config: &RenderConfig) -> String {
+    let mut s = String::new();
+    let package = records.first().map_or(String::new(), |r| escape_html(&r.package));
+
+    s.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    s.push_str(&format!("<title>{} changelog</title>\n", package));
+    s.push_str(&format!("<style>\n{}\n</style>\n", STYLE));
+    s.push_str("</head>\n<body>\n");
+    s.push_str(&format!("<h1>{}</h1>\n", package));
+    let finder = Finder::new();
+    for record in records.iter() {
+        s.push_str(&html_section(record, config, &finder));
+    }
+    s.push_str("</body>\n</html>\n");
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detail_lines;
+
+    #[test]
+    fn continuation_lines_lose_their_shared_indent() {
+        assert_eq!(detail_lines("Fix the build\n    with a continuation\n      and more."),
+                   vec!["Fix the build", "with a continuation", "  and more."]);
+        assert_eq!(detail_lines("One line."), vec!["One line."]);
+    }
+
+    #[test]
+    fn only_ascii_spaces_are_indent() {
+        assert_eq!(detail_lines("x\n a\n\u{a0}b"), vec!["x", " a", "\u{a0}b"]);
+        assert_eq!(detail_lines("x\n  a\n \u{a0}b"), vec!["x", " a", "\u{a0}b"]);
+        assert_eq!(detail_lines("x\n  a\n\u{a0}\n"), vec!["x", "a", "\u{a0}"]);
+    }
+}
@@ -0,0 +1,143 @@
+use record::Record;
+use version::DebianVersion;
+
+
+/// Range of entries to work with, same options as dpkg-parsechangelog.
+///
+/// Version bounds use Debian ordering:
+///
+///  * `since` - entries newer than the version, exclusive
+///  * `until` - entries older than the version, exclusive
+///  * `from`  - entries newer than or equal to the version
+///  * `to`    - entries older than or equal to the version
+///
+/// `since` takes precedence over `from` and `until` over `to`.
+///
+/// `offset` skips that many entries from the top (from the bottom if
+/// negative), `count` then keeps that many entries (the last ones if
+/// negative). When `count` or `offset` is given the version bounds are
+/// ignored, like dpkg does.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub since: Option<DebianVersion>,
+    pub until: Option<DebianVersion>,
+    pub from: Option<DebianVersion>,
+    pub to: Option<DebianVersion>,
+    pub count: Option<isize>,
+    pub offset: Option<isize>,
+}
+
+impl Selection {
+    /// Selects every entry.
+    pub fn new() -> Selection {
+        Selection {
+            since: None,
+            until: None,
+            from: None,
+            to: None,
+            count: None,
+            offset: None,
+        }
+    }
+
+    /// Selects the newest entry only, dpkg-parsechangelog's default.
+    pub fn latest() -> Selection {
+        let mut it = Selection::new();
+        it.count = Some(1);
+        it
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+            && self.from.is_none() && self.to.is_none()
+            && self.count.is_none() && self.offset.is_none()
+    }
+
+    fn is_positional(&self) -> bool {
+        self.count.is_some() || self.offset.is_some()
+    }
+
+    fn contains(&self, version: &DebianVersion) -> bool {
+        let lower = match (&self.since, &self.from) {
+            (&Some(ref since), _) => version > since,
+            (&None, &Some(ref from)) => version >= from,
+            (&None, &None) => true,
+        };
+
+        let upper = match (&self.until, &self.to) {
+            (&Some(ref until), _) => version < until,
+            (&None, &Some(ref to)) => version <= to,
+            (&None, &None) => true,
+        };
+
+        lower && upper
+    }
+
+    /// Applies the selection to `records`, which are newest first.
+    pub fn apply<'a>(&self, records: &'a [Record]) -> Vec<&'a Record> {
+        if !self.is_positional() {
+            return records.iter().filter(|r| self.contains(&r.version)).collect();
+        }
+
+        let len = records.len() as isize;
+
+        let start = match self.offset.unwrap_or(0) {
+            offset if offset < 0 => len.saturating_add(offset).max(0),
+            offset => offset.min(len),
+        } as usize;
+        let rest = &records[start..];
+
+        let picked = match self.count {
+            Some(count) if count < 0 => {
+                // -isize::MIN does not fit, but asks for more than there is
+                let count = count.checked_neg().map_or(rest.len(), |c| (c as usize).min(rest.len()));
+                &rest[rest.len() - count..]
+            },
+            Some(count) => &rest[..(count as usize).min(rest.len())],
+            None => rest,
+        };
+
+        picked.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::isize;
+
+    use changelog::Changelog;
+    use super::Selection;
+
+    fn versions(count: Option<isize>, offset: Option<isize>) -> Vec<String> {
+        let mut s = String::new();
+        for i in (1..6).rev() {
+            s.push_str(&format!("foo (1.{}) unstable; urgency=low\n\n  * Change.\n\n \
+                                 -- Carol <carol@example.com>  Mon, 01 Jan 2024 10:00:00 +0000\n\n",
+                                i));
+        }
+        let changelog = s.parse::<Changelog>().unwrap();
+
+        let mut selection = Selection::new();
+        selection.count = count;
+        selection.offset = offset;
+        selection.apply(&changelog.records).iter().map(|r| r.version.to_string()).collect()
+    }
+
+    #[test]
+    fn count_and_offset() {
+        assert_eq!(versions(Some(2), None), vec!["1.5", "1.4"]);
+        assert_eq!(versions(Some(-2), None), vec!["1.2", "1.1"]);
+        assert_eq!(versions(Some(2), Some(1)), vec!["1.4", "1.3"]);
+        assert_eq!(versions(None, Some(-2)), vec!["1.2", "1.1"]);
+        assert_eq!(versions(Some(9), Some(4)), vec!["1.1"]);
+    }
+
+    #[test]
+    fn extreme_values_do_not_overflow() {
+        assert_eq!(versions(Some(isize::MIN), None).len(), 5);
+        assert_eq!(versions(Some(isize::MAX), None).len(), 5);
+        assert_eq!(versions(None, Some(isize::MIN)).len(), 5);
+        assert_eq!(versions(None, Some(isize::MAX)).len(), 0);
+        assert_eq!(versions(Some(isize::MIN), Some(isize::MIN)).len(), 5);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use yaml_rust::Yaml;
+
+use changelog::Changelog;
+use version::{DebianVersion, VersionError};
+
+
+/// Ubuntu series codenames and their release numbers.
+pub const UBUNTU: &'static [(&'static str, &'static str)] = &[
+    ("focal", "20.04"),
+    ("jammy", "22.04"),
+    ("noble", "24.04"),
+    ("oracular", "24.10"),
+    ("plucky", "25.04"),
+    ("questing", "25.10"),
+];
+
+/// Where series variants go unless the Dchfile says otherwise, relative
+/// to the package.
+pub const DEFAULT_OUTPUT: &'static str = "debian/changelog.{series}";
+
+/// An Ubuntu series (or any other distribution) a PPA upload fans out to.
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    /// "22.04", becomes the "~22.04.1" version suffix
+    pub version: String,
+}
+
+impl Series {
+    /// Known Ubuntu series only, `None` otherwise.
+    pub fn new(name: &str) -> Option<Series> {
+        UBUNTU
+            .iter()
+            .find(|&&(codename, _)| codename == name)
+            .map(|&(codename, version)| Series {
+                name: codename.to_string(),
+                version: version.to_string(),
+            })
+    }
+
+    /// A Dchfile "series" item, either a known codename or a map for any
+    /// other series:
+    ///
+    /// ```yaml
+    /// series:
+    ///   - jammy
+    ///   - name: devel
+    ///     version: "26.04"
+    /// ```
+    pub fn from_yaml(yaml: &Yaml) -> Option<Series> {
+        if let Some(name) = yaml.as_str() {
+            return Series::new(name);
+        }
+
+        match (yaml["name"].as_str(), yaml["version"].as_str()) {
+            (Some(name), Some(version)) => Some(Series {
+                name: name.to_string(),
+                version: version.to_string(),
+            }),
+            (Some(name), None) => Series::new(name),
+            _ => None,
+        }
+    }
+
+    /// `template` with "{series}" replaced by the name.
+    pub fn path(&self, template: &str) -> String {
+        template.replace("{series}", &self.name)
+    }
+
+    /// `changelog` for this series: the newest entry gets the series as its
+    /// distribution and "~22.04.N" appended to its version, the other
+    /// entries are kept as they are. N goes up when `existing` (the
+    /// variant written before) already has an upload of the same version.
+    pub fn variant(&self, changelog: &Changelog, existing: Option<&Changelog>)
+        -> Result<Changelog, VersionError>
+    {
+        let mut it = Changelog::new();
+        it.leading = changelog.leading.to_vec();
+        it.records = changelog.records.iter().map(|r| r.copy()).collect();
+
+        let base = match it.records.first() {
+            Some(record) => record.version.to_string(),
+            None => return Ok(it),
+        };
+        let prefix = format!("{}~{}.", base, self.version);
+
+        let n = existing
+            .and_then(|e| e.records.first())
+            .and_then(|r| {
+                let version = r.version.to_string();
+                if version.starts_with(&prefix) {
+                    version[prefix.len()..].parse::<u64>().ok()
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(0);
+
+        let record = &mut it.records[0];
+        record.version = try!(format!("{}{}", prefix, n + 1).parse::<DebianVersion>());
+        record.distributions = vec![self.name.to_string()];
+
+        Ok(it)
+    }
+}
+
+/// Writes `variant` to `path`, creating directories as needed.
+pub fn write(variant: &mut Changelog, path: &str) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        try!(fs::create_dir_all(parent));
+    }
+    variant.to(path)
+}
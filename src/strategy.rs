@@ -0,0 +1,269 @@
+use std::fmt;
+use std::error;
+
+use chrono::UTC;
+use regex::Regex;
+
+use conventional;
+use conventional::ConventionalError;
+use git::Commit;
+use version::{DebianVersion, VersionError};
+
+
+#[derive(Debug)]
+pub enum StrategyError {
+    /// the strategy builds on the newest entry but the changelog is empty
+    NoPrevious(String),
+    /// the strategy needs the "version" key of the Dchfile
+    NoVersion(String),
+    Unknown(String),
+    /// a derived version does not sort against the one it is derived from
+    Unordered(DebianVersion, DebianVersion),
+    Version(VersionError),
+    Conventional(ConventionalError),
+}
+
+impl fmt::Display for StrategyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StrategyError::NoPrevious(ref name) => write!(f, "version strategy \"{}\" needs \
+                                                             a previous entry", name),
+            StrategyError::NoVersion(ref name) => write!(f, "version strategy \"{}\" needs \
+                                                            \"version\" in dchfile", name),
+            StrategyError::Unknown(ref name) => write!(f, "unknown version strategy \"{}\"",
+                                                       name),
+            StrategyError::Unordered(ref version, ref other) => write!(f, "derived version \
+                                                                       \"{}\" does not sort \
+                                                                       correctly against \"{}\"",
+                                                                       version, other),
+            StrategyError::Version(ref err) => write!(f, "{}", err),
+            StrategyError::Conventional(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<VersionError> for StrategyError {
+    fn from(err: VersionError) -> StrategyError {
+        StrategyError::Version(err)
+    }
+}
+
+impl From<ConventionalError> for StrategyError {
+    fn from(err: ConventionalError) -> StrategyError {
+        StrategyError::Conventional(err)
+    }
+}
+
+impl error::Error for StrategyError {
+    fn description(&self) -> &str {
+        match *self {
+            StrategyError::NoPrevious(..) => "no previous entry",
+            StrategyError::NoVersion(..) => "no version in dchfile",
+            StrategyError::Unknown(..) => "unknown version strategy",
+            StrategyError::Unordered(..) => "derived version does not sort correctly",
+            StrategyError::Version(ref err) => err.description(),
+            StrategyError::Conventional(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            StrategyError::Version(ref err) => Some(err),
+            StrategyError::Conventional(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Works out the version of a new changelog entry.
+pub trait VersionStrategy {
+    /// `previous` is the version of the newest entry, if there is one.
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError>;
+
+    /// Whether the new version has to sort above the newest entry. A
+    /// backport sorts below the entry it is based on.
+    fn above_previous(&self) -> bool {
+        true
+    }
+}
+
+fn require_previous<'a>(name: &str, previous: Option<&'a DebianVersion>)
+    -> Result<&'a DebianVersion, StrategyError>
+{
+    previous.ok_or(StrategyError::NoPrevious(name.to_string()))
+}
+
+/// strftime template expanded with the current UTC time, e.g. "%Y%m%d-1".
+pub struct Template {
+    pub template: String,
+}
+
+impl VersionStrategy for Template {
+    fn next(&self, _: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        Ok(try!(UTC::now().format(&self.template).to_string().parse::<DebianVersion>()))
+    }
+}
+
+/// Same upstream version, next debian revision: "1.2-3" to "1.2-4",
+/// "1.2-0ubuntu1" to "1.2-0ubuntu2". A revision without a trailing number
+/// gets "1" appended, a native version gets a trailing number bumped the
+/// same way.
+pub struct Revision;
+
+fn increment(s: &str) -> String {
+    let re = Regex::new(r"^(?P<head>.*?)(?P<number>\d+)$").unwrap();
+
+    match re.captures(s) {
+        Some(cap) => {
+            let number = cap.name("number").unwrap().parse::<u64>().unwrap_or(0);
+            format!("{}{}", cap.name("head").unwrap_or(""), number + 1)
+        },
+        None => format!("{}1", s),
+    }
+}
+
+impl VersionStrategy for Revision {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let previous = try!(require_previous("revision", previous));
+
+        Ok(try!(match previous.revision() {
+            Some(revision) => DebianVersion::new(previous.epoch(), previous.upstream(),
+                                                 Some(&increment(revision))),
+            None => DebianVersion::new(previous.epoch(), &increment(previous.upstream()), None),
+        }))
+    }
+}
+
+/// New upstream version with the debian revision starting over at "1";
+/// the epoch of the previous entry is kept.
+pub struct NewUpstream {
+    pub upstream: String,
+}
+
+impl VersionStrategy for NewUpstream {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let epoch = previous.map_or(0, |p| p.epoch());
+        Ok(try!(DebianVersion::new(epoch, &self.upstream, Some("1"))))
+    }
+}
+
+/// Date plus a counter of the releases made that day: "20261018.1",
+/// "20261018.2", ... A debian revision of the previous entry starts over
+/// at "1".
+pub struct Daily;
+
+impl VersionStrategy for Daily {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let today = UTC::now().format("%Y%m%d").to_string();
+
+        let sequence = previous
+            .and_then(|p| {
+                let mut parts = p.upstream().splitn(2, '.');
+                match (parts.next(), parts.next()) {
+                    (Some(day), Some(n)) if day == today => n.parse::<u64>().ok(),
+                    _ => None,
+                }
+            })
+            .map_or(1, |n| n + 1);
+
+        let epoch = previous.map_or(0, |p| p.epoch());
+        let revision = previous.and_then(|p| p.revision()).map(|_| "1");
+
+        Ok(try!(DebianVersion::new(epoch, &format!("{}.{}", today, sequence), revision)))
+    }
+}
+
+/// Next "~rcN" pre-release: "1.3~rc2-1" becomes "1.3~rc3-1". If the
+/// previous entry is not a release candidate of `upstream`, a new series
+/// starts at "~rc1". A debian revision of the previous entry starts over
+/// at "1", a native package stays native.
+pub struct ReleaseCandidate {
+    pub upstream: Option<String>,
+}
+
+impl VersionStrategy for ReleaseCandidate {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let re = Regex::new(r"^(?P<base>.+)~rc(?P<number>\d+)$").unwrap();
+
+        let epoch = previous.map_or(0, |p| p.epoch());
+        let current = previous.and_then(|p| re.captures(p.upstream()).map(|cap| (
+            cap.name("base").unwrap().to_string(),
+            cap.name("number").unwrap().parse::<u64>().unwrap_or(0),
+        )));
+
+        let upstream = match (current, self.upstream.as_ref()) {
+            (Some((ref base, _)), Some(upstream)) if base != upstream => format!("{}~rc1", upstream),
+            (Some((base, number)), _) => format!("{}~rc{}", base, number + 1),
+            (None, Some(upstream)) => format!("{}~rc1", upstream),
+            (None, None) => return Err(StrategyError::NoVersion("rc".to_string())),
+        };
+
+        let revision = previous.and_then(|p| p.revision()).map(|_| "1");
+
+        Ok(try!(DebianVersion::new(epoch, &upstream, revision)))
+    }
+}
+
+/// Semantic version bump from Conventional Commits, see `conventional`.
+pub struct Conventional {
+    pub commits: Vec<Commit>,
+}
+
+impl VersionStrategy for Conventional {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let previous = try!(require_previous("conventional-commits", previous));
+        Ok(try!(conventional::next(previous, &self.commits)))
+    }
+}
+
+/// Whether the strategy called `name` looks at the commits since the
+/// previous release.
+pub fn needs_commits(name: &str) -> bool {
+    name == "conventional-commits"
+}
+
+/// Strategy called `name` from the Dchfile. `version` is the Dchfile
+/// "version" key: the template for "template", the upstream version for
+/// "new-upstream" and "rc"; empty if not set.
+pub fn new(name: &str, version: &str, commits: Vec<Commit>)
+    -> Result<Box<VersionStrategy>, StrategyError>
+{
+    let upstream = if version.is_empty() { None } else { Some(version.to_string()) };
+
+    match name {
+        "template" => match upstream {
+            Some(template) => Ok(Box::new(Template { template: template })),
+            None => Err(StrategyError::NoVersion(name.to_string())),
+        },
+        "revision" => Ok(Box::new(Revision)),
+        "new-upstream" => match upstream {
+            Some(upstream) => Ok(Box::new(NewUpstream { upstream: upstream })),
+            None => Err(StrategyError::NoVersion(name.to_string())),
+        },
+        "daily" => Ok(Box::new(Daily)),
+        "rc" => Ok(Box::new(ReleaseCandidate { upstream: upstream })),
+        "conventional-commits" => Ok(Box::new(Conventional { commits: commits })),
+        _ => Err(StrategyError::Unknown(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use version::DebianVersion;
+    use super::{ReleaseCandidate, VersionStrategy};
+
+    fn rc(upstream: Option<&str>, previous: Option<&str>) -> String {
+        let strategy = ReleaseCandidate { upstream: upstream.map(|u| u.to_string()) };
+        let previous = previous.map(|p| p.parse::<DebianVersion>().unwrap());
+        strategy.next(previous.as_ref()).unwrap().to_string()
+    }
+
+    #[test]
+    fn release_candidates_keep_the_kind_of_package() {
+        assert_eq!(rc(None, Some("1:1.3~rc2-4")), "1:1.3~rc3-1");
+        assert_eq!(rc(None, Some("1.2~rc1")), "1.2~rc2");
+        assert_eq!(rc(Some("1.4"), Some("1.3~rc2")), "1.4~rc1");
+        assert_eq!(rc(Some("1.4"), Some("1.3-2")), "1.4~rc1-1");
+        assert_eq!(rc(Some("1.4"), None), "1.4~rc1");
+    }
+}
@@ -0,0 +1,460 @@
+use std::fmt;
+use std::error;
+use std::env;
+use std::collections::HashMap;
+
+use chrono::UTC;
+use chrono::format::{Item, StrftimeItems};
+use regex::Regex;
+
+
+const DEFAULT_DATE_FORMAT: &'static str = "%Y-%m-%d";
+
+
+// line and column of a tag within the template, both starting at 1
+type Position = (usize, usize);
+
+/// Every error carries the line and column of the offending tag within the
+/// template.
+#[derive(Debug)]
+pub enum TemplateError {
+    /// malformed tag, with the text of the tag
+    Syntax(String, usize, usize),
+    /// `{% if %}` or `{% for %}` without its end tag
+    Unclosed(String, usize, usize),
+    Unknown(String, usize, usize),
+    /// a known variable without a value, e.g. "git.sha" outside of a git
+    /// repository
+    Unset(String, usize, usize),
+    NotList(String, usize, usize),
+}
+
+impl TemplateError {
+    pub fn position(&self) -> (usize, usize) {
+        match *self {
+            TemplateError::Syntax(_, line, column) => (line, column),
+            TemplateError::Unclosed(_, line, column) => (line, column),
+            TemplateError::Unknown(_, line, column) => (line, column),
+            TemplateError::Unset(_, line, column) => (line, column),
+            TemplateError::NotList(_, line, column) => (line, column),
+        }
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(match *self {
+            TemplateError::Syntax(ref tag, ..) => write!(f, "invalid template tag \"{}\"", tag),
+            TemplateError::Unclosed(ref tag, ..) => write!(f, "\"{{% {} %}}\" is never closed",
+                                                           tag),
+            TemplateError::Unknown(ref name, ..) => write!(f, "unknown template variable \"{}\"",
+                                                           name),
+            TemplateError::Unset(ref name, ..) => write!(f, "template variable \"{}\" is not set",
+                                                         name),
+            TemplateError::NotList(ref name, ..) => write!(f, "template variable \"{}\" is not \
+                                                               a list", name),
+        });
+        let (line, column) = self.position();
+        write!(f, " at {}:{}", line, column)
+    }
+}
+
+impl error::Error for TemplateError {
+    fn description(&self) -> &str {
+        match *self {
+            TemplateError::Syntax(..) => "invalid template tag",
+            TemplateError::Unclosed(..) => "unclosed template block",
+            TemplateError::Unknown(..) => "unknown template variable",
+            TemplateError::Unset(..) => "template variable is not set",
+            TemplateError::NotList(..) => "template variable is not a list",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Str(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        match *self {
+            Value::Str(ref s) => !s.is_empty(),
+            Value::List(ref l) => !l.is_empty(),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        match *self {
+            Value::Str(ref s) => s.to_string(),
+            Value::List(ref l) => l.join(", "),
+        }
+    }
+}
+
+/// Variables a template can use. Besides the ones set here there are
+/// `date` (`{{ date "%Y%m%d" }}`, UTC now) and `env.NAME` for environment
+/// variables.
+pub struct Context {
+    values: HashMap<String, Option<Value>>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context {
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.values.insert(name.to_string(), Some(Value::Str(value.to_string())));
+    }
+
+    pub fn set_list(&mut self, name: &str, values: Vec<String>) {
+        self.values.insert(name.to_string(), Some(Value::List(values)));
+    }
+
+    /// Sets `name` if there is a value, otherwise only makes it known:
+    /// `{% if name %}` is then false and `{{ name }}` an `Unset` error.
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) {
+        self.values.insert(name.to_string(), value.map(|v| Value::Str(v.to_string())));
+    }
+}
+
+enum Node {
+    Text(String),
+    Var(String, Option<String>, Position),
+    If(bool, String, Vec<Node>, Vec<Node>, Position),
+    For(String, String, Vec<Node>, Position),
+}
+
+enum Token {
+    Text(String),
+    Var(String, Position),
+    Tag(String, Position),
+}
+
+fn position_of(template: &str, offset: usize) -> Position {
+    let before = &template[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(i) => before[i + 1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+    (line, column)
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>, TemplateError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut rest = template;
+
+    loop {
+        let start = match (rest.find("{{"), rest.find("{%")) {
+            (Some(a), Some(b)) => if a < b { a } else { b },
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => break,
+        };
+
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+
+        let (line, column) = position_of(template, template.len() - rest.len() + start);
+        let is_var = rest[start..].starts_with("{{");
+        let close = if is_var { "}}" } else { "%}" };
+        let end = match rest[start + 2..].find(close) {
+            Some(end) => start + 2 + end,
+            None => return Err(TemplateError::Syntax(rest[start..].to_string(), line, column)),
+        };
+
+        let inner = rest[start + 2..end].trim().to_string();
+        tokens.push(if is_var {
+            Token::Var(inner, (line, column))
+        } else {
+            Token::Tag(inner, (line, column))
+        });
+        rest = &rest[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+
+    Ok(tokens)
+}
+
+// Expressions for the insides of tags, compiled once per template.
+struct Patterns {
+    var: Regex,
+    if_: Regex,
+    for_: Regex,
+}
+
+impl Patterns {
+    fn new() -> Patterns {
+        Patterns {
+            var: Regex::new(r#"^(?P<name>[A-Za-z_][\w.]*)(?:\s+"(?P<arg>[^"]*)")?$"#).unwrap(),
+            if_: Regex::new(r"^if\s+(?P<not>not\s+)?(?P<name>[A-Za-z_][\w.]*)$").unwrap(),
+            for_: Regex::new(r"^for\s+(?P<var>\w+)\s+in\s+(?P<list>[A-Za-z_][\w.]*)$").unwrap(),
+        }
+    }
+}
+
+// Parses nodes up to one of the `ends` tags, which is returned as well.
+fn parse(patterns: &Patterns, tokens: &[Token], at: &mut usize, ends: &[&str])
+    -> Result<(Vec<Node>, Option<(String, Position)>), TemplateError>
+{
+    let mut nodes: Vec<Node> = Vec::new();
+
+    while *at < tokens.len() {
+        let token = &tokens[*at];
+        *at += 1;
+
+        match *token {
+            Token::Text(ref text) => nodes.push(Node::Text(text.to_string())),
+            Token::Var(ref var, (line, column)) => match patterns.var.captures(var) {
+                Some(cap) => nodes.push(Node::Var(
+                    cap.name("name").unwrap().to_string(),
+                    cap.name("arg").map(|a| a.to_string()),
+                    (line, column))),
+                None => return Err(TemplateError::Syntax(format!("{{{{ {} }}}}", var),
+                                                         line, column)),
+            },
+            Token::Tag(ref tag, (line, column)) => {
+                if ends.contains(&&tag[..]) {
+                    return Ok((nodes, Some((tag.to_string(), (line, column)))));
+                }
+
+                let unclosed = || TemplateError::Unclosed(tag.to_string(), line, column);
+
+                if let Some(cap) = patterns.if_.captures(tag) {
+                    let (then, end) = try!(parse(patterns, tokens, at, &["else", "endif"]));
+                    let otherwise = match end {
+                        Some((ref end, _)) if end == "else" => {
+                            match try!(parse(patterns, tokens, at, &["endif"])) {
+                                (otherwise, Some(..)) => otherwise,
+                                (_, None) => return Err(unclosed()),
+                            }
+                        },
+                        Some(..) => Vec::new(),
+                        None => return Err(unclosed()),
+                    };
+                    nodes.push(Node::If(
+                        cap.name("not").is_some(),
+                        cap.name("name").unwrap().to_string(),
+                        then, otherwise, (line, column)));
+                } else if let Some(cap) = patterns.for_.captures(tag) {
+                    let body = match try!(parse(patterns, tokens, at, &["endfor"])) {
+                        (body, Some(..)) => body,
+                        (_, None) => return Err(unclosed()),
+                    };
+                    nodes.push(Node::For(
+                        cap.name("var").unwrap().to_string(),
+                        cap.name("list").unwrap().to_string(),
+                        body, (line, column)));
+                } else {
+                    return Err(TemplateError::Syntax(format!("{{% {} %}}", tag), line, column));
+                }
+            },
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+struct Renderer<'a> {
+    context: &'a Context,
+    // loop variables, innermost last
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl<'a> Renderer<'a> {
+    // `Ok(None)` for a known variable without a value.
+    fn lookup(&self, name: &str, arg: Option<&str>, (line, column): Position)
+        -> Result<Option<Value>, TemplateError>
+    {
+        for scope in self.scopes.iter().rev() {
+            if let Some(value) = scope.get(name) {
+                return Ok(Some(value.clone()));
+            }
+        }
+
+        if name == "date" {
+            let format = arg.unwrap_or(DEFAULT_DATE_FORMAT);
+            // chrono panics formatting an invalid specifier
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(TemplateError::Syntax(format!("{{{{ {} \"{}\" }}}}", name, format),
+                                                 line, column));
+            }
+            return Ok(Some(Value::Str(UTC::now().format(format).to_string())));
+        }
+
+        if name.starts_with("env.") {
+            return Ok(env::var(&name[4..]).ok().map(Value::Str));
+        }
+
+        match self.context.values.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(TemplateError::Unknown(name.to_string(), line, column)),
+        }
+    }
+
+    fn render(&mut self, nodes: &[Node], out: &mut String) -> Result<(), TemplateError> {
+        for node in nodes.iter() {
+            match *node {
+                Node::Text(ref text) => out.push_str(text),
+                Node::Var(ref name, ref arg, (line, column)) => {
+                    match try!(self.lookup(name, arg.as_ref().map(|a| &a[..]), (line, column))) {
+                        Some(value) => out.push_str(&value.to_string()),
+                        None => return Err(TemplateError::Unset(name.to_string(), line, column)),
+                    }
+                },
+                Node::If(not, ref name, ref then, ref otherwise, position) => {
+                    let value = try!(self.lookup(name, None, position))
+                        .map_or(false, |v| v.is_true());
+                    if value != not {
+                        try!(self.render(then, out));
+                    } else {
+                        try!(self.render(otherwise, out));
+                    }
+                },
+                Node::For(ref var, ref name, ref body, (line, column)) => {
+                    let items = match try!(self.lookup(name, None, (line, column))) {
+                        Some(Value::List(items)) => items,
+                        Some(Value::Str(..)) =>
+                            return Err(TemplateError::NotList(name.to_string(), line, column)),
+                        None => Vec::new(),
+                    };
+
+                    for (i, item) in items.iter().enumerate() {
+                        let mut scope: HashMap<String, Value> = HashMap::new();
+                        let flag = |b: bool| Value::Str(if b { "true" } else { "" }.to_string());
+                        scope.insert(var.to_string(), Value::Str(item.to_string()));
+                        scope.insert("loop.index".to_string(), Value::Str((i + 1).to_string()));
+                        scope.insert("loop.first".to_string(), flag(i == 0));
+                        scope.insert("loop.last".to_string(), flag(i + 1 == items.len()));
+
+                        self.scopes.push(scope);
+                        let rendered = self.render(body, out);
+                        self.scopes.pop();
+                        try!(rendered);
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders a Dchfile template:
+///
+/// - `{{ name }}` and `{{ date "%Y%m%d" }}` print values, lists are joined
+///   with ", "
+/// - `{% if name %}...{% else %}...{% endif %}`, also `if not name`
+/// - `{% for bug in closes %}#{{ bug }}{% if not loop.last %}, {% endif %}{% endfor %}`
+///
+/// Unknown variables are errors, including inside `if`; only `env.NAME`
+/// and known variables without a value count as false there.
+pub fn render(template: &str, context: &Context) -> Result<String, TemplateError> {
+    let tokens = try!(tokenize(template));
+
+    let mut at = 0;
+    let nodes = match try!(parse(&Patterns::new(), &tokens, &mut at, &[])) {
+        (nodes, None) => nodes,
+        (_, Some((end, (line, column)))) =>
+            return Err(TemplateError::Syntax(format!("{{% {} %}}", end), line, column)),
+    };
+
+    let mut renderer = Renderer {
+        context: context,
+        scopes: Vec::new(),
+    };
+    let mut out = String::new();
+    try!(renderer.render(&nodes, &mut out));
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Context, TemplateError};
+
+    fn context() -> Context {
+        let mut context = Context::new();
+        context.set("version", "1.0-1");
+        context.set_list("closes", vec!["1".to_string(), "2".to_string()]);
+        context.set_option("git.sha", None);
+        context
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let context = context();
+        assert_eq!(render("v{{ version }}", &context).unwrap(), "v1.0-1");
+        assert_eq!(render("{{version}} closes {{ closes }}", &context).unwrap(),
+                   "1.0-1 closes 1, 2");
+        assert_eq!(render("{% if git.sha %}{{ git.sha }}{% else %}none{% endif %}", &context)
+                   .unwrap(), "none");
+        assert_eq!(render("{% for bug in closes %}#{{ bug }}{% if not loop.last %}, \
+                           {% endif %}{% endfor %}", &context).unwrap(), "#1, #2");
+    }
+
+    #[test]
+    fn text_without_tags_is_kept() {
+        assert_eq!(render("no tags here", &Context::new()).unwrap(), "no tags here");
+        assert_eq!(render("", &Context::new()).unwrap(), "");
+    }
+
+    #[test]
+    fn unknown_variable_is_an_error() {
+        match render("{{ version }} {{ verison }}", &context()) {
+            Err(TemplateError::Unknown(ref name, 1, 15)) if name == "verison" => {},
+            other => panic!("expected Unknown at 1:15, got {:?}", other),
+        }
+        match render("{% if nope %}x{% endif %}", &context()) {
+            Err(TemplateError::Unknown(ref name, 1, 1)) if name == "nope" => {},
+            other => panic!("expected Unknown at 1:1, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn known_variable_without_value_is_unset() {
+        match render("sha: {{ git.sha }}", &context()) {
+            Err(TemplateError::Unset(ref name, 1, 6)) if name == "git.sha" => {},
+            other => panic!("expected Unset at 1:6, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_tag() {
+        let context = context();
+
+        let err = render("a\nbb {{ version\n", &context).unwrap_err();
+        assert_eq!(err.position(), (2, 4));
+        assert_eq!(err.to_string(), "invalid template tag \"{{ version\n\" at 2:4");
+
+        let err = render("{{ version }}\n  {% if version %}", &context).unwrap_err();
+        match err {
+            TemplateError::Unclosed(..) => {},
+            ref other => panic!("expected Unclosed, got {:?}", other),
+        }
+        assert_eq!(err.position(), (2, 3));
+
+        assert_eq!(render("x {% endif %}", &context).unwrap_err().position(), (1, 3));
+        assert_eq!(render("{{ 1version }}", &context).unwrap_err().position(), (1, 1));
+        assert_eq!(render("ü {% for v in version %}{% endfor %}", &context)
+                   .unwrap_err().position(), (1, 3));
+    }
+
+    #[test]
+    fn invalid_date_format_is_an_error() {
+        assert_eq!(render("{{ date \"%Y\" }}", &Context::new()).unwrap().len(), 4);
+
+        let err = render("at {{ date \"%Y%\" }}", &Context::new()).unwrap_err();
+        assert_eq!(err.position(), (1, 4));
+        assert_eq!(err.to_string(), "invalid template tag \"{{ date \"%Y%\" }}\" at 1:4");
+        assert!(render("{{ date \"%Q\" }}", &Context::new()).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use regex::Regex;
+
+use record::{Record, MantainerDetails};
+use strategy::{StrategyError, VersionStrategy};
+use version::DebianVersion;
+
+
+/// Kind of upload the new entry is for, following the Debian Developer's
+/// Reference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Upload {
+    Maintainer,
+    NMU,
+    QA,
+    Team,
+    BinNMU,
+}
+
+impl Upload {
+    pub fn parse(s: &str) -> Option<Upload> {
+        match &s.to_lowercase()[..] {
+            "maintainer" => Some(Upload::Maintainer),
+            "nmu" => Some(Upload::NMU),
+            "qa" => Some(Upload::QA),
+            "team" => Some(Upload::Team),
+            "binnmu" => Some(Upload::BinNMU),
+            _ => None,
+        }
+    }
+
+    /// Bullet every entry of this kind has to start with.
+    pub fn bullet(&self) -> Option<&'static str> {
+        match *self {
+            Upload::Maintainer => None,
+            Upload::NMU => Some("Non-maintainer upload."),
+            Upload::QA => Some("QA upload."),
+            Upload::Team => Some("Team upload."),
+            Upload::BinNMU => Some("Binary-only non-maintainer upload; no source changes."),
+        }
+    }
+
+    /// Version scheme imposed by the upload kind; `None` leaves the choice
+    /// to the package's own version strategy.
+    pub fn strategy(&self) -> Option<Box<VersionStrategy>> {
+        match *self {
+            Upload::NMU => Some(Box::new(NonMaintainer)),
+            Upload::BinNMU => Some(Box::new(BinNMU)),
+            _ => None,
+        }
+    }
+
+    /// Puts the mandatory bullet first and marks binNMUs "binary-only".
+    pub fn apply(&self, record: &mut Record) {
+        if *self == Upload::BinNMU {
+            record.set_option("binary-only", "yes");
+        }
+
+        let bullet = match self.bullet() {
+            Some(bullet) => bullet.to_string(),
+            None => return,
+        };
+
+        if record.mantainer_details.iter().any(|md| md.details.contains(&bullet)) {
+            return;
+        }
+
+        match record.mantainer_details.first_mut() {
+            Some(md) if md.mantainer.is_empty() => {
+                md.details.insert(0, bullet);
+                return;
+            },
+            _ => {},
+        }
+
+        record.mantainer_details.insert(0, MantainerDetails::with_details(vec![bullet]));
+    }
+}
+
+// "1.2+nmu1" -> ("1.2", 1)
+fn suffix(s: &str, separator: &str) -> Option<(String, u64)> {
+    let re = Regex::new(&format!(r"^(?P<base>.+){}(?P<number>\d+)$", separator)).unwrap();
+    re.captures(s).map(|cap| (
+        cap.name("base").unwrap().to_string(),
+        cap.name("number").unwrap().parse::<u64>().unwrap_or(0),
+    ))
+}
+
+/// NMU versions: "1.2-3" becomes "1.2-3.1" and a native "1.2" becomes
+/// "1.2+nmu1"; another NMU on top of those gives "1.2-3.2" and "1.2+nmu2".
+pub struct NonMaintainer;
+
+impl VersionStrategy for NonMaintainer {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Err(StrategyError::NoPrevious("nmu".to_string())),
+        };
+
+        Ok(try!(match previous.revision() {
+            Some(revision) => {
+                let revision = match suffix(revision, r"\.") {
+                    Some((base, n)) => format!("{}.{}", base, n + 1),
+                    None => format!("{}.1", revision),
+                };
+                DebianVersion::new(previous.epoch(), previous.upstream(), Some(&revision))
+            },
+            None => {
+                let upstream = match suffix(previous.upstream(), r"\+nmu") {
+                    Some((base, n)) => format!("{}+nmu{}", base, n + 1),
+                    None => format!("{}+nmu1", previous.upstream()),
+                };
+                DebianVersion::new(previous.epoch(), &upstream, None)
+            },
+        }))
+    }
+}
+
+/// binNMU versions: "+b1" appended to the previous version, "+b2" after
+/// another binNMU.
+pub struct BinNMU;
+
+impl VersionStrategy for BinNMU {
+    fn next(&self, previous: Option<&DebianVersion>) -> Result<DebianVersion, StrategyError> {
+        let previous = match previous {
+            Some(previous) => previous.to_string(),
+            None => return Err(StrategyError::NoPrevious("binnmu".to_string())),
+        };
+
+        let version = match suffix(&previous, r"\+b") {
+            Some((base, n)) => format!("{}+b{}", base, n + 1),
+            None => format!("{}+b1", previous),
+        };
+
+        Ok(try!(version.parse::<DebianVersion>()))
+    }
+}
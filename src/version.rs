@@ -0,0 +1,324 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::error;
+use std::str::FromStr;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionError {
+    Empty,
+    Epoch(String),
+    UpstreamEmpty,
+    UpstreamCharacter(char),
+    RevisionEmpty,
+    RevisionCharacter(char),
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VersionError::Empty => write!(f, "version is empty"),
+            VersionError::Epoch(ref epoch) => write!(f, "epoch \"{}\" is not a number", epoch),
+            VersionError::UpstreamEmpty => write!(f, "upstream version is empty"),
+            VersionError::UpstreamCharacter(c) => write!(f, "invalid character '{}' in \
+                                                             upstream version", c),
+            VersionError::RevisionEmpty => write!(f, "debian revision is empty"),
+            VersionError::RevisionCharacter(c) => write!(f, "invalid character '{}' in \
+                                                             debian revision", c),
+        }
+    }
+}
+
+impl error::Error for VersionError {
+    fn description(&self) -> &str {
+        match *self {
+            VersionError::Empty => "version is empty",
+            VersionError::Epoch(..) => "epoch is not a number",
+            VersionError::UpstreamEmpty => "upstream version is empty",
+            VersionError::UpstreamCharacter(..) => "invalid character in upstream version",
+            VersionError::RevisionEmpty => "debian revision is empty",
+            VersionError::RevisionCharacter(..) => "invalid character in debian revision",
+        }
+    }
+}
+
+/// `[epoch:]upstream_version[-debian_revision]` as described in Debian
+/// Policy 5.6.12, ordered the way dpkg orders versions.
+#[derive(Debug, Clone)]
+pub struct DebianVersion {
+    epoch: u32,
+    upstream: String,
+    revision: Option<String>,
+}
+
+impl DebianVersion {
+    /// An upstream version should start with a digit, but like dpkg this
+    /// accepts old ones that do not ("cvs20020101"), see
+    /// `starts_with_digit`.
+    pub fn new(epoch: u32, upstream: &str, revision: Option<&str>) -> Result<DebianVersion, VersionError> {
+        if upstream.is_empty() {
+            return Err(VersionError::UpstreamEmpty);
+        }
+
+        for c in upstream.chars() {
+            let allowed = c.is_ascii_alphanumeric()
+                || c == '.' || c == '+' || c == '~'
+                || (c == '-' && revision.is_some());
+            if !allowed {
+                return Err(VersionError::UpstreamCharacter(c));
+            }
+        }
+
+        if let Some(revision) = revision {
+            if revision.is_empty() {
+                return Err(VersionError::RevisionEmpty);
+            }
+
+            for c in revision.chars() {
+                if !(c.is_ascii_alphanumeric() || c == '.' || c == '+' || c == '~') {
+                    return Err(VersionError::RevisionCharacter(c));
+                }
+            }
+        }
+
+        Ok(DebianVersion {
+            epoch: epoch,
+            upstream: upstream.to_string(),
+            revision: revision.map(|r| r.to_string()),
+        })
+    }
+
+    pub fn epoch(&self) -> u32 { self.epoch }
+
+    pub fn upstream(&self) -> &str { &self.upstream }
+
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_ref().map(|r| &r[..])
+    }
+
+    /// Whether the upstream version starts with a digit as Debian Policy
+    /// asks.
+    pub fn starts_with_digit(&self) -> bool {
+        self.upstream.chars().next().map_or(false, |c| c.is_digit(10))
+    }
+
+    /// Native packages have no debian revision.
+    pub fn is_native(&self) -> bool { self.revision.is_none() }
+}
+
+impl Default for DebianVersion {
+    fn default() -> DebianVersion {
+        DebianVersion {
+            epoch: 0,
+            upstream: "0".to_string(),
+            revision: None,
+        }
+    }
+}
+
+impl FromStr for DebianVersion {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<DebianVersion, VersionError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(VersionError::Empty);
+        }
+
+        let (epoch, rest) = match s.find(':') {
+            Some(i) => match s[..i].parse::<u32>() {
+                Ok(epoch) => (epoch, &s[i + 1..]),
+                Err(..) => return Err(VersionError::Epoch(s[..i].to_string())),
+            },
+            None => (0, s),
+        };
+
+        match rest.rfind('-') {
+            Some(i) => DebianVersion::new(epoch, &rest[..i], Some(&rest[i + 1..])),
+            None => DebianVersion::new(epoch, rest, None),
+        }
+    }
+}
+
+impl fmt::Display for DebianVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.epoch > 0 {
+            try!(write!(f, "{}:", self.epoch));
+        }
+
+        try!(write!(f, "{}", self.upstream));
+
+        match self.revision {
+            Some(ref revision) => write!(f, "-{}", revision),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Ord for DebianVersion {
+    fn cmp(&self, other: &DebianVersion) -> Ordering {
+        self.epoch.cmp(&other.epoch)
+            .then_with(|| verrevcmp(&self.upstream, &other.upstream))
+            .then_with(|| verrevcmp(
+                self.revision().unwrap_or(""),
+                other.revision().unwrap_or("")))
+    }
+}
+
+impl PartialOrd for DebianVersion {
+    fn partial_cmp(&self, other: &DebianVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// "1.0" and "1.00" are the same version for dpkg, so equality has to
+// agree with the ordering rather than with the strings.
+impl PartialEq for DebianVersion {
+    fn eq(&self, other: &DebianVersion) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DebianVersion {}
+
+// Weight of a non-digit character, see `order()` in dpkg's lib/dpkg/version.c:
+// "~" sorts before everything, even the end of the part, letters sort before
+// all other characters.
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if (c as char).is_digit(10) => 0,
+        Some(c) if (c as char).is_ascii_alphabetic() => c as i32,
+        Some(b'~') => -1,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+fn is_digit(c: Option<u8>) -> bool {
+    match c {
+        Some(c) => (c as char).is_digit(10),
+        None => false,
+    }
+}
+
+/// Port of dpkg's `verrevcmp`: alternately compares non-digit prefixes
+/// character by character and digit runs numerically.
+pub fn verrevcmp(a: &str, b: &str) -> Ordering {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let at = |s: &[u8], i: usize| s.get(i).cloned();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !is_digit(at(a, i)))
+            || (j < b.len() && !is_digit(at(b, j))) {
+            let ac = order(at(a, i));
+            let bc = order(at(b, j));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') { i += 1; }
+        while b.get(j) == Some(&b'0') { j += 1; }
+
+        let mut first_diff = Ordering::Equal;
+        while is_digit(at(a, i)) && is_digit(at(b, j)) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        if is_digit(at(a, i)) { return Ordering::Greater; }
+        if is_digit(at(b, j)) { return Ordering::Less; }
+        if first_diff != Ordering::Equal { return first_diff; }
+    }
+
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::cmp::Ordering::{Equal, Greater, Less};
+
+    use super::DebianVersion;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        a.parse::<DebianVersion>().unwrap().cmp(&b.parse::<DebianVersion>().unwrap())
+    }
+
+    // results of `dpkg --compare-versions A lt|eq|gt B`
+    const DPKG: &'static [(&'static str, &'static str, Ordering)] = &[
+        ("1.0~rc1", "1.0", Less),
+        ("1.0~", "1.0", Less),
+        ("1.0~~", "1.0~", Less),
+        ("1.0~~a", "1.0~~", Greater),
+        ("1.0", "1.0.1", Less),
+        ("1.0-1", "1.0-2", Less),
+        ("1:0.9", "2.0", Greater),
+        ("1:1.0", "1.0", Greater),
+        ("2:1.0", "1:9.9", Greater),
+        ("1.0", "1.00", Equal),
+        ("1.01", "1.1", Equal),
+        ("1.002", "1.2", Equal),
+        ("1.0a", "1.0+", Less),
+        ("1.0a", "1.0.", Less),
+        ("1.0a", "1.0b", Less),
+        ("1.0", "1.0-0", Equal),
+        ("1.0", "1.0-1", Less),
+        ("1.0-0", "1.0-0.1", Less),
+        ("1.2-3~bpo12+1", "1.2-3", Less),
+        ("1.2-3+deb12u1", "1.2-3", Greater),
+        ("1.2-3~bpo11+1", "1.2-3~bpo12+1", Less),
+        ("9", "10", Less),
+        ("1.0+dfsg-1", "1.0-1", Greater),
+        ("0:1.0", "1.0", Equal),
+        ("2.0-1~rc1", "2.0-1", Less),
+        ("1.0", "1.0a", Less),
+    ];
+
+    #[test]
+    fn orders_like_dpkg() {
+        for &(a, b, expected) in DPKG.iter() {
+            assert_eq!(cmp(a, b), expected, "{} vs {}", a, b);
+            assert_eq!(cmp(b, a), expected.reverse(), "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn equal_versions_are_eq() {
+        assert_eq!("1.0".parse::<DebianVersion>().unwrap(),
+                   "0:1.00-0".parse::<DebianVersion>().unwrap());
+        assert!("1.0-1".parse::<DebianVersion>().unwrap()
+                != "1.0-2".parse::<DebianVersion>().unwrap());
+    }
+
+    #[test]
+    fn accepts_upstream_not_starting_with_a_digit() {
+        let version = "cvs20020101-1".parse::<DebianVersion>().unwrap();
+        assert_eq!(version.upstream(), "cvs20020101");
+        assert!(!version.starts_with_digit());
+        assert!("1.0".parse::<DebianVersion>().unwrap().starts_with_digit());
+        assert!(version > "1.0-1".parse::<DebianVersion>().unwrap());
+    }
+
+    #[test]
+    fn parses_parts() {
+        let version = "2:1.2-3-4".parse::<DebianVersion>().unwrap();
+        assert_eq!(version.epoch(), 2);
+        assert_eq!(version.upstream(), "1.2-3");
+        assert_eq!(version.revision(), Some("4"));
+        assert_eq!(version.to_string(), "2:1.2-3-4");
+
+        assert!("1.0".parse::<DebianVersion>().unwrap().is_native());
+        assert!("".parse::<DebianVersion>().is_err());
+        assert!("1.0_1".parse::<DebianVersion>().is_err());
+        assert!("1.0-".parse::<DebianVersion>().is_err());
+        assert!("x:1.0".parse::<DebianVersion>().is_err());
+    }
+}